@@ -0,0 +1,93 @@
+//! Timing programs over several runs, e.g. to measure what the optimizer
+//! gains.
+
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// The wall times of several runs of a program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// How long each run took, in order.
+    pub times: Vec<Duration>,
+    /// How many steps each run took.
+    pub steps: u64,
+}
+
+impl Stats {
+    /// The mean wall time.
+    pub fn mean(&self) -> Duration {
+        self.times.iter().sum::<Duration>() / self.times.len().max(1) as u32
+    }
+
+    /// The median wall time.
+    pub fn median(&self) -> Duration {
+        let mut times = self.times.clone();
+        times.sort();
+
+        match times.len() {
+            0 => Duration::ZERO,
+            n if n % 2 == 0 => (times[n / 2 - 1] + times[n / 2]) / 2,
+            n => times[n / 2],
+        }
+    }
+
+    /// The (population) standard deviation of the wall times.
+    pub fn stddev(&self) -> Duration {
+        let mean = self.mean().as_secs_f64();
+        let variance = self
+            .times
+            .iter()
+            .map(|time| (time.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.times.len().max(1) as f64;
+
+        Duration::from_secs_f64(variance.sqrt())
+    }
+
+    /// How many steps were run per second, on average.
+    pub fn steps_per_sec(&self) -> f64 {
+        self.steps as f64 / self.mean().as_secs_f64()
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rate = self.steps_per_sec();
+        let (rate, unit) = if rate >= 1e9 {
+            (rate / 1e9, "G")
+        } else if rate >= 1e6 {
+            (rate / 1e6, "M")
+        } else if rate >= 1e3 {
+            (rate / 1e3, "k")
+        } else {
+            (rate, "")
+        };
+
+        write!(
+            f,
+            "mean {:>9.3?}  median {:>9.3?}  stddev {:>9.3?}  {rate:>6.1}{unit} steps/s",
+            self.mean(),
+            self.median(),
+            self.stddev(),
+        )
+    }
+}
+
+/// Calls `run` `warmup` times, then times it `runs` times. `run` returns how
+/// many steps it took.
+pub fn measure(runs: usize, warmup: usize, mut run: impl FnMut() -> u64) -> Stats {
+    for _ in 0..warmup {
+        run();
+    }
+
+    let mut times = Vec::with_capacity(runs);
+    let mut steps = 0;
+
+    for _ in 0..runs {
+        let start = Instant::now();
+        steps = run();
+        times.push(start.elapsed());
+    }
+
+    Stats { times, steps }
+}
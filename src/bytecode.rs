@@ -0,0 +1,302 @@
+//! A versioned binary format for optimized [`Token`] streams.
+//!
+//! The header records the machine configuration the program was compiled
+//! for, since [`Token`]s hold [`Cell`]s and brackets hold indices that
+//! [`optimize`](crate::token::optimize) has already resolved. Loading a
+//! program compiled for a different configuration is refused.
+//!
+//! Layout (all integers little-endian):
+//!
+//! - magic: `b"BRIMBC"`
+//! - version: `u16`
+//! - cell width in bytes: `u8`
+//! - flags: `u8` (bit 0: signed cells, bit 1: non-wrapping cells)
+//! - tape length: `u32` (0 if dynamic)
+//! - token count: `u64`
+//! - tokens: an opcode `u8`, then its operands (cells at cell width, offsets
+//!   as `i64`, indices as `u64`)
+
+use std::fmt::Display;
+use std::mem::size_of;
+
+use crate::token::Token;
+use crate::Cell;
+
+/// Identifies a bytecode file.
+pub const MAGIC: &[u8; 6] = b"BRIMBC";
+
+/// The current version of the format.
+pub const VERSION: u16 = 1;
+
+/// The machine configuration a program was compiled for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The width of each cell, in bytes.
+    pub cell_width: u8,
+    /// Whether cells are signed.
+    pub signed: bool,
+    /// Whether cells wrap on over/underflow.
+    pub wrapping: bool,
+    /// The length of the tape, or 0 if it's dynamic.
+    pub tape_len: u32,
+}
+
+impl Config {
+    /// The configuration of this build.
+    pub const fn current() -> Self {
+        Self {
+            cell_width: size_of::<Cell>() as u8,
+            signed: cfg!(feature = "signed_cell"),
+            wrapping: !cfg!(feature = "nowrap"),
+            tape_len: if cfg!(feature = "dynamic_array") {
+                0
+            } else {
+                30000
+            },
+        }
+    }
+}
+
+impl Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-bit {} {} cells, ",
+            self.cell_width as u32 * 8,
+            if self.signed { "signed" } else { "unsigned" },
+            if self.wrapping {
+                "wrapping"
+            } else {
+                "non-wrapping"
+            },
+        )?;
+
+        if self.tape_len == 0 {
+            write!(f, "dynamic tape")
+        } else {
+            write!(f, "{}-cell tape", self.tape_len)
+        }
+    }
+}
+
+/// An error encountered while loading bytecode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The input doesn't start with [`MAGIC`].
+    NotBytecode,
+    /// The input was written by an unsupported version of the format.
+    Version(u16),
+    /// The input was compiled for a different machine configuration.
+    Mismatch { expected: Config, found: Config },
+    /// The input contains an unknown opcode.
+    Opcode(u8),
+    /// The input contains a bracket that doesn't point at its pair.
+    Bracket(usize),
+    /// The input ended early.
+    Truncated,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotBytecode => write!(f, "not a brim bytecode file"),
+            Error::Version(v) => write!(f, "unsupported version {v} (expected {VERSION})"),
+            Error::Mismatch { expected, found } => {
+                write!(f, "compiled for {found}, but this build uses {expected}")
+            }
+            Error::Opcode(op) => write!(f, "unknown opcode 0x{op:02x}"),
+            Error::Bracket(i) => write!(f, "mismatched bracket at token {i}"),
+            Error::Truncated => write!(f, "unexpected end of file"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Returns true if `bytes` looks like bytecode.
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Serializes optimized tokens, alongside the current [`Config`].
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+pub fn serialize(code: &[Token]) -> Vec<u8> {
+    let config = Config::current();
+    let mut out = Vec::new();
+
+    out.extend(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.push(config.cell_width);
+    out.push(config.signed as u8 | (!config.wrapping as u8) << 1);
+    out.extend(config.tape_len.to_le_bytes());
+    out.extend((code.len() as u64).to_le_bytes());
+
+    for tok in code {
+        match *tok {
+            Token::Inc(i) => {
+                out.push(0x00);
+                out.extend(i.to_le_bytes());
+            }
+            Token::Dec(i) => {
+                out.push(0x01);
+                out.extend(i.to_le_bytes());
+            }
+            Token::Goto(i) => {
+                out.push(0x02);
+                out.extend((i as i64).to_le_bytes());
+            }
+            Token::LBrack(i) => {
+                out.push(0x03);
+                out.extend((i as u64).to_le_bytes());
+            }
+            Token::RBrack(i) => {
+                out.push(0x04);
+                out.extend((i as u64).to_le_bytes());
+            }
+            Token::Out => out.push(0x05),
+            Token::In => out.push(0x06),
+            Token::Zero => out.push(0x07),
+            Token::Set(i) => {
+                out.push(0x08);
+                out.extend(i.to_le_bytes());
+            }
+            Token::Add(i, mul) => {
+                out.push(0x09);
+                out.extend((i as i64).to_le_bytes());
+                out.extend(mul.to_le_bytes());
+            }
+            Token::Sub(i) => {
+                out.push(0x0a);
+                out.extend((i as i64).to_le_bytes());
+            }
+            Token::Dup(i1, mul1, i2, mul2) => {
+                out.push(0x0b);
+                out.extend((i1 as i64).to_le_bytes());
+                out.extend(mul1.to_le_bytes());
+                out.extend((i2 as i64).to_le_bytes());
+                out.extend(mul2.to_le_bytes());
+            }
+            Token::Scan(i) => {
+                out.push(0x0c);
+                out.extend((i as i64).to_le_bytes());
+            }
+            Token::End => out.push(0x0d),
+
+            Token::Dump => out.push(0x0e),
+        }
+    }
+
+    out
+}
+
+/// Deserializes tokens written by [`serialize`]. The result is ready to be
+/// interpreted.
+///
+/// Fails if the bytecode was compiled for a different [`Config`] than
+/// [`Config::current`].
+pub fn deserialize(bytes: &[u8]) -> Result<Vec<Token>, Error> {
+    if !is_bytecode(bytes) {
+        return Err(Error::NotBytecode);
+    }
+
+    let mut r = Reader {
+        bytes,
+        pos: MAGIC.len(),
+    };
+
+    let version = u16::from_le_bytes(r.array()?);
+    if version != VERSION {
+        return Err(Error::Version(version));
+    }
+
+    let cell_width = r.byte()?;
+    let flags = r.byte()?;
+    let found = Config {
+        cell_width,
+        signed: flags & 1 != 0,
+        wrapping: flags & 2 == 0,
+        tape_len: u32::from_le_bytes(r.array()?),
+    };
+
+    let expected = Config::current();
+    if found != expected {
+        return Err(Error::Mismatch { expected, found });
+    }
+
+    let len = u64::from_le_bytes(r.array()?) as usize;
+    let mut code = Vec::with_capacity(len.min(bytes.len()));
+
+    for _ in 0..len {
+        let tok = match r.byte()? {
+            0x00 => Token::Inc(r.cell()?),
+            0x01 => Token::Dec(r.cell()?),
+            0x02 => Token::Goto(r.offset()?),
+            0x03 => Token::LBrack(r.index()?),
+            0x04 => Token::RBrack(r.index()?),
+            0x05 => Token::Out,
+            0x06 => Token::In,
+            0x07 => Token::Zero,
+            0x08 => Token::Set(r.cell()?),
+            0x09 => Token::Add(r.offset()?, r.cell()?),
+            0x0a => Token::Sub(r.offset()?),
+            0x0b => Token::Dup(r.offset()?, r.cell()?, r.offset()?, r.cell()?),
+            0x0c => Token::Scan(r.offset()?),
+            0x0d => Token::End,
+
+            0x0e => Token::Dump,
+
+            op => return Err(Error::Opcode(op)),
+        };
+
+        code.push(tok);
+    }
+
+    for (i, tok) in code.iter().enumerate() {
+        let ok = match *tok {
+            Token::LBrack(j) => matches!(code.get(j), Some(&Token::RBrack(k)) if k == i),
+            Token::RBrack(j) => matches!(code.get(j), Some(&Token::LBrack(k)) if k == i),
+            _ => true,
+        };
+
+        if !ok {
+            return Err(Error::Bracket(i));
+        }
+    }
+
+    Ok(code)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or(Error::Truncated)?;
+        self.pos += N;
+
+        Ok(bytes.try_into().expect("slice has length N"))
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn cell(&mut self) -> Result<Cell, Error> {
+        Ok(Cell::from_le_bytes(self.array()?))
+    }
+
+    fn offset(&mut self) -> Result<isize, Error> {
+        Ok(i64::from_le_bytes(self.array()?) as isize)
+    }
+
+    fn index(&mut self) -> Result<usize, Error> {
+        Ok(u64::from_le_bytes(self.array()?) as usize)
+    }
+}
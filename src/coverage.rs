@@ -0,0 +1,99 @@
+//! Source coverage reports, built from a [`Profile`].
+//!
+//! Each optimized token is mapped back to the source it came from, so a
+//! macro-token like `[->+<]` covers all of its original instructions at once.
+//! Instructions the optimizer removed entirely (e.g. `+-`) don't count as
+//! code.
+
+use std::fmt::Write;
+
+use crate::profile::Profile;
+use crate::program::Program;
+
+/// Coverage for a single line of source code.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    /// The most times any instruction on this line ran, or `None` if the line
+    /// has no code.
+    pub count: Option<u64>,
+    /// The 1-based columns of instructions that never ran.
+    pub missed: Vec<usize>,
+}
+
+/// Works out the coverage of each line of `program`, which must have its
+/// source.
+pub fn lines(program: &Program, profile: &Profile) -> Vec<Line> {
+    let source = &program.source;
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut lines = vec![Line::default(); starts.len()];
+
+    for (span, &count) in program.spans.iter().zip(&profile.counts) {
+        for (offset, ch) in source[span.start..span.end].char_indices() {
+            if !program.options.is_command(ch) {
+                continue;
+            }
+
+            let offset = span.start + offset;
+            let line = starts.partition_point(|&start| start <= offset) - 1;
+            let col = source[starts[line]..offset].chars().count() + 1;
+            let line = &mut lines[line];
+
+            line.count = Some(line.count.unwrap_or(0).max(count));
+            if count == 0 {
+                line.missed.push(col);
+            }
+        }
+    }
+
+    lines
+}
+
+/// Writes a copy of the source in the style of `gcov`, with each line
+/// prefixed by its execution count (`-` if it has no code, `#####` if none of
+/// it ran). Lines with code that never ran are followed by a line marking it.
+pub fn annotate(program: &Program, profile: &Profile, name: &str) -> String {
+    let mut out = format!("{:>9}:{:>5}:Source:{name}\n", "-", 0);
+
+    let lines = lines(program, profile);
+    for (n, (text, line)) in program.source.lines().zip(&lines).enumerate() {
+        let count = match line.count {
+            Some(0) => "#####".to_string(),
+            Some(count) => count.to_string(),
+            None => "-".to_string(),
+        };
+        let _ = writeln!(out, "{count:>9}:{:>5}:{text}", n + 1);
+
+        if line.count.is_some_and(|count| count != 0) && !line.missed.is_empty() {
+            let width = line.missed.last().copied().unwrap_or(0);
+            let marks: String = (1..=width)
+                .map(|col| if line.missed.contains(&col) { '^' } else { ' ' })
+                .collect();
+
+            let _ = writeln!(out, "{:>9} {:>5} {marks}", "", "");
+        }
+    }
+
+    out
+}
+
+/// Writes an lcov tracefile record, which most coverage tools can read.
+pub fn lcov(program: &Program, profile: &Profile, name: &str) -> String {
+    let mut out = format!("TN:\nSF:{name}\n");
+    let (mut found, mut hit) = (0, 0);
+
+    for (n, line) in lines(program, profile).iter().enumerate() {
+        if let Some(count) = line.count {
+            let _ = writeln!(out, "DA:{},{count}", n + 1);
+
+            found += 1;
+            if count != 0 {
+                hit += 1;
+            }
+        }
+    }
+
+    let _ = writeln!(out, "LF:{found}\nLH:{hit}\nend_of_record");
+    out
+}
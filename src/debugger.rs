@@ -0,0 +1,449 @@
+//! An interactive, line-based debugger.
+//!
+//! Commands are read one per line; an empty line repeats the last command.
+//! Run `help` inside the debugger for a list.
+
+use std::io::{self, Write};
+
+use crate::helper::{line_col, offset_of, parse_range};
+use crate::history::History;
+use crate::machine::{Machine, State};
+use crate::program::Program;
+use crate::watch::Watch;
+use crate::Cell;
+
+/// How many cells past its end the tape may be grown by `set` or `ptr`, with
+/// feature `dynamic_array`.
+const MAX_GROWTH: usize = 30000;
+
+const HELP: &str = "\
+commands:
+    step [n]           (s)  run one token, or n tokens
+    continue           (c)  run until a breakpoint, or the end
+    step-back [n]      (sb) undo one token, or n tokens
+    reverse-continue   (rc) run backwards until a breakpoint, or the start
+    break <loc>        (b)  set a breakpoint at `line:col`, `line`, or token `#index`
+    delete <n>         (d)  remove breakpoint n
+    breakpoints        (bl) list breakpoints
+    watch [cells]           stop whenever a cell (`37`) or range (`30..40`)
+                            changes, or list watchpoints
+    unwatch <n>             remove watchpoint n
+    print [cells]      (p)  print the cells around the pointer, a cell (`37`),
+                            or a range of cells (`30..40`)
+    set <cell> <value>      change a cell
+    ptr [cell]              print or move the tape pointer
+    list               (l)  show the source around the current token
+    where              (w)  show the current token
+    help               (h)  show this message
+    quit               (q)  exit the debugger";
+
+/// A debugging session for a single program.
+pub struct Debugger<'a> {
+    /// The machine being debugged.
+    pub machine: Machine<'a>,
+    program: &'a Program,
+    breakpoints: Vec<usize>,
+    watch: Watch,
+    history: History,
+    state: State,
+}
+
+impl<'a> Debugger<'a> {
+    /// Creates a debugger for `program`. Without its source, e.g. if it was
+    /// loaded from bytecode, tokens are shown by index instead.
+    pub fn new(program: &'a Program) -> Self {
+        Self::with_machine(program, Machine::new(&program.code))
+    }
+
+    /// Creates a debugger for `program`, starting from `machine`, e.g. one
+    /// with an initial tape.
+    pub fn with_machine(program: &'a Program, mut machine: Machine<'a>) -> Self {
+        machine.dump.program = Some(program);
+        let history = History::new(&machine);
+        let state = if machine.finished() {
+            State::Finished
+        } else {
+            State::Running
+        };
+
+        Self {
+            machine,
+            program,
+            breakpoints: Vec::new(),
+            watch: Watch::new(true),
+            history,
+            state,
+        }
+    }
+
+    /// Runs the debugger until `commands` (e.g.
+    /// [`BufRead::lines`](std::io::BufRead::lines)) runs out
+    /// or the user quits. The program reads from `stdin` and writes to
+    /// `stdout`, while the debugger itself writes to `ui`.
+    pub fn run(
+        &mut self,
+        commands: &mut impl Iterator<Item = io::Result<String>>,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+        ui: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut last = String::new();
+
+        self.location(ui)?;
+
+        loop {
+            write!(ui, "(brim) ")?;
+            ui.flush()?;
+
+            let Some(line) = commands.next().transpose()? else {
+                writeln!(ui)?;
+                return Ok(());
+            };
+
+            let line = line.trim();
+            if !line.is_empty() {
+                last = line.to_string();
+            }
+
+            let keep_going = self.command(&last, stdin, stdout, ui)?;
+            stdout.flush()?;
+
+            if !keep_going {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a single command. Returns false if the user quit.
+    fn command(
+        &mut self,
+        line: &str,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+        ui: &mut impl Write,
+    ) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let Some(cmd) = words.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = words.collect();
+
+        match (cmd, args.as_slice()) {
+            ("s" | "step", []) => self.resume(Some(1), stdin, stdout, ui)?,
+            ("s" | "step", [n]) => match n.parse() {
+                Ok(n) => self.resume(Some(n), stdin, stdout, ui)?,
+                Err(_) => writeln!(ui, "invalid step count `{n}`")?,
+            },
+            ("c" | "continue", []) => self.resume(None, stdin, stdout, ui)?,
+            ("sb" | "step-back", []) => self.reverse(Some(1), ui)?,
+            ("sb" | "step-back", [n]) => match n.parse() {
+                Ok(n) => self.reverse(Some(n), ui)?,
+                Err(_) => writeln!(ui, "invalid step count `{n}`")?,
+            },
+            ("rc" | "reverse-continue", []) => self.reverse(None, ui)?,
+
+            ("b" | "break", [loc]) => match self.resolve(loc) {
+                Some(i) if self.breakpoints.contains(&i) => {
+                    writeln!(ui, "there is already a breakpoint at #{i}")?
+                }
+                Some(i) => {
+                    self.breakpoints.push(i);
+                    write!(ui, "breakpoint {} at ", self.breakpoints.len())?;
+                    self.describe(i, ui)?;
+                }
+                None => writeln!(ui, "no token at `{loc}`")?,
+            },
+            ("d" | "delete", [n]) => match n.parse::<usize>() {
+                Ok(n) if (1..=self.breakpoints.len()).contains(&n) => {
+                    self.breakpoints.remove(n - 1);
+                }
+                _ => writeln!(ui, "no breakpoint `{n}`")?,
+            },
+            ("bl" | "breakpoints", []) => {
+                if self.breakpoints.is_empty() {
+                    writeln!(ui, "no breakpoints")?;
+                }
+
+                for (n, &i) in self.breakpoints.iter().enumerate() {
+                    write!(ui, "{}: ", n + 1)?;
+                    self.describe(i, ui)?;
+                }
+            }
+
+            ("watch", []) => {
+                if self.watch.ranges.is_empty() {
+                    writeln!(ui, "no watchpoints")?;
+                }
+
+                for (n, range) in self.watch.ranges.iter().enumerate() {
+                    writeln!(ui, "{}: cells {}..{}", n + 1, range.start, range.end)?;
+                }
+            }
+            ("watch", [cells]) => match parse_range(cells) {
+                Some((start, end)) => {
+                    self.watch.ranges.push(start..end);
+                    writeln!(
+                        ui,
+                        "watchpoint {} on cells {start}..{end}",
+                        self.watch.ranges.len()
+                    )?;
+                }
+                None => writeln!(ui, "invalid cell or range `{cells}`")?,
+            },
+            ("unwatch", [n]) => match n.parse::<usize>() {
+                Ok(n) if (1..=self.watch.ranges.len()).contains(&n) => {
+                    self.watch.ranges.remove(n - 1);
+                }
+                _ => writeln!(ui, "no watchpoint `{n}`")?,
+            },
+
+            ("p" | "print", []) => {
+                let sp = self.machine.sp;
+                print_cells(&self.machine.tape, sp, sp.saturating_sub(8), sp + 8, ui)?;
+            }
+            ("p" | "print", [cells]) => match parse_range(cells) {
+                Some((start, end)) => {
+                    print_cells(&self.machine.tape, self.machine.sp, start, end, ui)?
+                }
+                None => writeln!(ui, "invalid cell or range `{cells}`")?,
+            },
+            ("set", [cell, value]) => match (cell.parse::<usize>(), value.parse::<Cell>()) {
+                (Ok(cell), Ok(value)) if self.in_bounds(cell) => {
+                    if cell >= self.machine.tape.len() {
+                        self.machine.tape.resize(cell + 1, 0);
+                    }
+
+                    self.machine.tape[cell] = value;
+                    self.history.reset(&self.machine);
+                }
+                (Ok(cell), Ok(_)) => writeln!(ui, "cell {cell} is off the tape")?,
+                _ => writeln!(ui, "usage: set <cell> <value>")?,
+            },
+            ("ptr", []) => writeln!(ui, "sp = {}", self.machine.sp)?,
+            ("ptr", [cell]) => match cell.parse::<usize>() {
+                Ok(cell) if self.in_bounds(cell) => {
+                    if cell >= self.machine.tape.len() {
+                        self.machine.tape.resize(cell + 1, 0);
+                    }
+
+                    self.machine.sp = cell;
+                    self.history.reset(&self.machine);
+                }
+                Ok(cell) => writeln!(ui, "cell {cell} is off the tape")?,
+                _ => writeln!(ui, "invalid cell `{cell}`")?,
+            },
+
+            ("l" | "list", []) => self.list(ui)?,
+            ("w" | "where", []) => self.location(ui)?,
+
+            ("h" | "help", _) => writeln!(ui, "{HELP}")?,
+            ("q" | "quit", _) => return Ok(false),
+
+            _ => writeln!(ui, "unknown command `{line}`; try `help`")?,
+        }
+
+        Ok(true)
+    }
+
+    /// Runs up to `limit` tokens (or forever), stopping at breakpoints and
+    /// watchpoints.
+    fn resume(
+        &mut self,
+        limit: Option<u64>,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+        ui: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut steps = 0;
+
+        while self.state == State::Running {
+            let state = self
+                .history
+                .step(&mut self.machine, &mut self.watch, stdin, stdout);
+            steps += 1;
+
+            if !self.watch.hits.is_empty() {
+                stdout.flush()?;
+                for hit in self.watch.hits.drain(..) {
+                    writeln!(ui, "watchpoint: {}", hit.describe(self.program))?;
+                }
+            }
+
+            if state == State::Stopped {
+                break;
+            }
+            self.state = state;
+
+            if limit.is_some_and(|limit| steps >= limit) {
+                break;
+            }
+
+            if let Some(n) = self.breakpoints.iter().position(|&i| i == self.machine.ip) {
+                if self.state == State::Running {
+                    stdout.flush()?;
+                    writeln!(ui, "hit breakpoint {}", n + 1)?;
+                    break;
+                }
+            }
+        }
+
+        stdout.flush()?;
+        self.location(ui)
+    }
+
+    /// Undoes up to `limit` tokens (or as many as possible), stopping at
+    /// breakpoints.
+    fn reverse(&mut self, limit: Option<u64>, ui: &mut impl Write) -> io::Result<()> {
+        let mut steps = 0;
+
+        while limit.is_none_or(|limit| steps < limit) {
+            if self.history.step_back(&mut self.machine, 1) == 0 {
+                writeln!(ui, "reached the start of the recorded history")?;
+                break;
+            }
+
+            self.state = State::Running;
+            steps += 1;
+
+            if limit.is_none() {
+                if let Some(n) = self.breakpoints.iter().position(|&i| i == self.machine.ip) {
+                    writeln!(ui, "hit breakpoint {}", n + 1)?;
+                    break;
+                }
+            }
+        }
+
+        self.location(ui)
+    }
+
+    /// Reports the current state of the program.
+    fn location(&self, ui: &mut impl Write) -> io::Result<()> {
+        let m = &self.machine;
+
+        match self.state {
+            State::Finished => {
+                return writeln!(ui, "program finished after {} steps", m.steps);
+            }
+            State::Hanging => writeln!(ui, "program hangs forever (`[]` on a nonzero cell)")?,
+            State::Running | State::Stopped => {}
+        }
+
+        write!(
+            ui,
+            "sp = {}, cell = {}, steps = {}; next: ",
+            m.sp,
+            m.cell(m.sp),
+            m.steps
+        )?;
+        self.describe(m.ip, ui)
+    }
+
+    /// Describes token `i`, with its source position.
+    fn describe(&self, i: usize, ui: &mut impl Write) -> io::Result<()> {
+        writeln!(ui, "{}", self.program.describe(i))
+    }
+
+    /// Finds the token at a location: `#index`, `line:col`, or `line`.
+    fn resolve(&self, loc: &str) -> Option<usize> {
+        if let Some(i) = loc.strip_prefix('#') {
+            return i.parse().ok().filter(|&i| i < self.machine.code.len());
+        }
+
+        let (line, col) = loc.split_once(':').unwrap_or((loc, "1"));
+        let offset = offset_of(&self.program.source, line.parse().ok()?, col.parse().ok()?)?;
+
+        self.program.spans.iter().position(|span| span.end > offset)
+    }
+
+    /// Returns true if `cell` is on the tape. With feature `dynamic_array`,
+    /// the tape can grow to fit, but only by up to [`MAX_GROWTH`] cells at a
+    /// time.
+    fn in_bounds(&self, cell: usize) -> bool {
+        let mut len = self.machine.tape.len();
+        if cfg!(feature = "dynamic_array") {
+            len += MAX_GROWTH;
+        }
+
+        cell < len
+    }
+
+    /// Prints the source around the current token, underlining it.
+    fn list(&self, ui: &mut impl Write) -> io::Result<()> {
+        let source = &self.program.source;
+        if self.program.spans.is_empty() {
+            return writeln!(ui, "no source; the program was loaded from bytecode");
+        }
+        let Some(span) = self.program.spans.get(self.machine.ip) else {
+            return writeln!(ui, "no current token");
+        };
+
+        let (line, col) = line_col(source, span.start);
+        let (end_line, end_col) = line_col(source, span.end);
+        let width = if end_line == line { end_col - col } else { 1 };
+
+        let first = line.saturating_sub(3).max(1);
+        for (n, text) in source.lines().enumerate().skip(first - 1).take(7) {
+            let n = n + 1;
+            let mark = if n == line { '>' } else { ' ' };
+            writeln!(ui, "{mark}{n:>5} | {text}")?;
+
+            if n == line {
+                writeln!(
+                    ui,
+                    "       | {:col$}{}",
+                    "",
+                    "^".repeat(width),
+                    col = col - 1
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Prints cells `start..end` of `tape`, marking the one at `sp`. With feature
+/// `dynamic_array`, up to [`MAX_GROWTH`] cells past the end are shown as 0.
+pub(crate) fn print_cells(
+    tape: &[Cell],
+    sp: usize,
+    start: usize,
+    end: usize,
+    ui: &mut impl Write,
+) -> io::Result<()> {
+    let end = if cfg!(feature = "dynamic_array") {
+        end.min(tape.len() + MAX_GROWTH)
+    } else {
+        end.min(tape.len())
+    };
+
+    for (n, i) in (start..end).enumerate() {
+        if n != 0 {
+            write!(ui, "{}", if n % 8 == 0 { "\n" } else { "  " })?;
+        }
+
+        let mark = if i == sp { '*' } else { ' ' };
+        write!(ui, "{mark}{i:>5}: {:>4}", tape.get(i).copied().unwrap_or(0))?;
+    }
+
+    writeln!(ui)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_cells_stops_past_the_tape() {
+        let mut out = Vec::new();
+        print_cells(&[1, 2, 3], 0, 0, usize::MAX, &mut out).unwrap();
+
+        let cells = String::from_utf8(out).unwrap().matches(':').count();
+        let expected = if cfg!(feature = "dynamic_array") {
+            3 + MAX_GROWTH
+        } else {
+            3
+        };
+        assert_eq!(cells, expected);
+    }
+}
@@ -0,0 +1,254 @@
+//! Formatting for [`Dump`](crate::token::Token::Dump) (`;` by default).
+
+use std::fmt::{self, Debug, Write as _};
+use std::io::{self, stderr, Write};
+use std::str::FromStr;
+
+use crate::helper::line_col;
+use crate::program::Program;
+use crate::token::Token;
+use crate::Cell;
+
+/// How to display each cell.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Hexadecimal, e.g. `0x2a` (`hex`).
+    #[default]
+    Hex,
+    /// Unsigned decimal, e.g. `42` (`dec`).
+    Decimal,
+    /// Signed decimal, e.g. `-1` (`signed`).
+    Signed,
+    /// A character, e.g. `'*'` or `'\n'`, falling back to decimal outside of
+    /// ASCII (`ascii`).
+    Ascii,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(Self::Hex),
+            "dec" => Ok(Self::Decimal),
+            "signed" => Ok(Self::Signed),
+            "ascii" => Ok(Self::Ascii),
+            _ => Err(format!(
+                "expected `hex`, `dec`, `signed` or `ascii`, found `{s}`"
+            )),
+        }
+    }
+}
+
+impl Format {
+    /// Formats a cell.
+    pub fn cell(self, cell: Cell) -> String {
+        let bits = Cell::BITS;
+        #[allow(clippy::unnecessary_cast)]
+        let unsigned = cell as u64 & (u64::MAX >> (64 - bits));
+        let signed = ((unsigned << (64 - bits)) as i64) >> (64 - bits);
+
+        match self {
+            Self::Hex => format!("0x{unsigned:0width$x}", width = bits as usize / 4),
+            Self::Decimal => unsigned.to_string(),
+            Self::Signed => signed.to_string(),
+            Self::Ascii => match u8::try_from(unsigned) {
+                Ok(byte) if byte.is_ascii() => {
+                    format!("'{}'", std::ascii::escape_default(byte))
+                }
+                _ => unsigned.to_string(),
+            },
+        }
+    }
+}
+
+/// Settings for [`Dump`](Token::Dump).
+pub struct DumpConfig<'a> {
+    /// How many cells to print per line.
+    pub width: usize,
+    /// How many cells to print on either side of the pointer. If `None`, the
+    /// tape is printed from the start up to the furthest the pointer has been.
+    pub window: Option<usize>,
+    /// How to display each cell.
+    pub format: Format,
+    /// Whether to write each dump as a single line of JSON instead, for other
+    /// tools to read.
+    pub json: bool,
+    /// The program being run. If given, its source code is shown for context
+    /// instead of the tokens around the dump.
+    pub program: Option<&'a Program>,
+    /// Where to write dumps. Defaults to stderr.
+    pub out: Option<Box<dyn Write + 'a>>,
+}
+
+impl Default for DumpConfig<'_> {
+    fn default() -> Self {
+        Self {
+            width: 8,
+            window: None,
+            format: Format::Hex,
+            json: false,
+            program: None,
+            out: None,
+        }
+    }
+}
+
+impl Debug for DumpConfig<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DumpConfig")
+            .field("width", &self.width)
+            .field("window", &self.window)
+            .field("format", &self.format)
+            .field("json", &self.json)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The state of the machine at a dump.
+pub(crate) struct DumpState<'a> {
+    pub code: &'a [Token],
+    pub tape: &'a [Cell],
+    pub ip: usize,
+    pub sp: usize,
+    pub steps: u64,
+    pub highest: usize,
+}
+
+impl DumpConfig<'_> {
+    /// Writes a dump of `state` to the configured output.
+    pub(crate) fn write(&mut self, state: &DumpState) -> io::Result<()> {
+        let text = if self.json {
+            self.json(state)
+        } else {
+            self.text(state)
+        };
+
+        match &mut self.out {
+            Some(out) => out.write_all(text.as_bytes()),
+            None => stderr().write_all(text.as_bytes()),
+        }
+    }
+
+    /// The range of cells to print.
+    fn cells(&self, state: &DumpState) -> (usize, usize) {
+        let (start, end) = match self.window {
+            Some(window) => (state.sp.saturating_sub(window), state.sp + window + 1),
+            None => (0, (state.highest + 1).max(8)),
+        };
+
+        (start, end.min(state.tape.len()))
+    }
+
+    fn text(&self, state: &DumpState) -> String {
+        let mut out = format!("\nsp: 0x{:04x}   ", state.sp);
+
+        match self.context(state) {
+            Some((line, col, context)) => {
+                let _ = write!(out, "at {line}:{col}: {context}");
+            }
+            None => {
+                let (code, ip) = (state.code, state.ip);
+                let left = match ip {
+                    0 => " ".to_string(),
+                    _ => code[ip - 1].to_string(),
+                };
+                let right = match code.get(ip + 1) {
+                    Some(tok) => tok.to_string(),
+                    None => " ".to_string(),
+                };
+
+                let _ = write!(out, "ctx: {left} {} {right}", code[ip]);
+            }
+        }
+
+        let width = self.width.max(1);
+        let (start, end) = self.cells(state);
+        for (n, i) in (start..end).enumerate() {
+            if n % width == 0 {
+                out.push('\n');
+            } else {
+                out.push_str(" | ");
+            }
+
+            let mark = if i == state.sp { '*' } else { ' ' };
+            let _ = write!(out, "{mark}0x{i:04x} : {}", self.format.cell(state.tape[i]));
+        }
+
+        out.push('\n');
+        out
+    }
+
+    fn json(&self, state: &DumpState) -> String {
+        let (start, end) = self.cells(state);
+        let mut out = format!(
+            "{{\"step\":{},\"ip\":{},\"sp\":{},",
+            state.steps, state.ip, state.sp
+        );
+
+        if let Some((line, col, context)) = self.context(state) {
+            let _ = write!(
+                out,
+                "\"line\":{line},\"col\":{col},\"context\":{},",
+                json_string(&context)
+            );
+        }
+
+        let cells: Vec<String> = state.tape[start..end].iter().map(Cell::to_string).collect();
+        let _ = writeln!(out, "\"start\":{start},\"cells\":[{}]}}", cells.join(","));
+
+        out
+    }
+
+    /// The source position of the dump, and the source code around it.
+    fn context(&self, state: &DumpState) -> Option<(usize, usize, String)> {
+        const CONTEXT: usize = 20;
+
+        let program = self.program?;
+        let span = program.spans.get(state.ip)?;
+        let source = &program.source;
+        let (line, col) = line_col(source, span.start);
+
+        let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.end..]
+            .find('\n')
+            .map_or(source.len(), |i| span.end + i);
+
+        let before: String = {
+            let text = &source[line_start..span.start];
+            let skip = text.chars().count().saturating_sub(CONTEXT);
+            text.chars().skip(skip).collect()
+        };
+        let after: String = source[span.end..line_end].chars().take(CONTEXT).collect();
+
+        let context = format!(
+            "{}{}{}",
+            before.trim_start(),
+            &source[span.start..span.end],
+            after.trim_end()
+        );
+
+        Some((line, col, context))
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            ch if ch.is_control() => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+
+    out.push('"');
+    out
+}
@@ -0,0 +1,25 @@
+//! Backends that translate optimized [`Token`](crate::token::Token)s into
+//! other languages.
+//!
+//! Apart from [`bf`] and [`ir`], every backend targets the default machine: a
+//! 30000-cell tape that wraps around at both ends, made of wrapping byte-sized
+//! cells. `In` stores 0 on EOF, as in [`interpret`](crate::interpret). Debug
+//! dumps are ignored.
+
+pub mod asm;
+pub mod bf;
+pub mod elf;
+pub mod ir;
+pub mod llvm;
+pub mod wat;
+pub mod x86;
+
+/// The length of the tape in emitted programs.
+pub const TAPE_LEN: usize = 30000;
+
+/// Reduces a relative move to an offset in `0..TAPE_LEN`, so that moving
+/// becomes an addition followed by at most one subtraction.
+#[inline]
+pub fn offset(i: isize) -> usize {
+    i.rem_euclid(TAPE_LEN as isize) as usize
+}
@@ -0,0 +1,151 @@
+//! x86-64 assembly backend, for Linux.
+//!
+//! The output is GAS syntax (with `.intel_syntax noprefix`), and uses raw
+//! `read`/`write` syscalls, so it needs no libc:
+//! `as prog.s -o prog.o && ld prog.o -o prog`.
+
+use std::fmt::Write;
+
+use super::{offset, TAPE_LEN};
+use crate::token::Token;
+
+/// Translates `code` into x86-64 assembly.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+#[allow(clippy::unnecessary_cast)]
+pub fn emit(code: &[Token]) -> String {
+    let mut out = String::new();
+    let mut loops = Vec::new();
+    let mut labels = 0;
+
+    let _ = writeln!(
+        out,
+        "    .intel_syntax noprefix\n    .globl _start\n\n\
+         \x20   .bss\ntape:\n    .zero {TAPE_LEN}\n\n\
+         \x20   .text\n_start:\n\
+         \x20   lea rbx, [rip + tape]\n\
+         \x20   xor r12d, r12d"
+    );
+
+    for tok in code {
+        match *tok {
+            Token::Inc(i) => inst(&mut out, &format!("add byte ptr [rbx+r12], {}", i as u8)),
+            Token::Dec(i) => inst(&mut out, &format!("sub byte ptr [rbx+r12], {}", i as u8)),
+            Token::Goto(i) => goto(&mut out, "r12", offset(i)),
+
+            Token::In => {
+                inst(&mut out, "xor eax, eax\nxor edi, edi");
+                syscall_cell(&mut out);
+                inst(&mut out, "test rax, rax\njg 1f\nmov byte ptr [rbx+r12], 0");
+                out.push_str("1:\n");
+            }
+            Token::Out => {
+                inst(&mut out, "mov eax, 1\nmov edi, 1");
+                syscall_cell(&mut out);
+            }
+
+            Token::LBrack(_) => {
+                labels += 1;
+                loops.push(labels);
+
+                inst(
+                    &mut out,
+                    &format!("cmp byte ptr [rbx+r12], 0\nje .Lexit{labels}"),
+                );
+                let _ = writeln!(out, ".Lloop{labels}:");
+            }
+            Token::RBrack(_) => {
+                let id = loops.pop().expect("brackets should be matched");
+
+                inst(
+                    &mut out,
+                    &format!("cmp byte ptr [rbx+r12], 0\njne .Lloop{id}"),
+                );
+                let _ = writeln!(out, ".Lexit{id}:");
+            }
+
+            Token::Zero => inst(&mut out, "mov byte ptr [rbx+r12], 0"),
+            Token::Set(i) => inst(&mut out, &format!("mov byte ptr [rbx+r12], {}", i as u8)),
+            Token::Add(i, mul) => {
+                add_mul(&mut out, i, mul as u8, "add");
+                inst(&mut out, "mov byte ptr [rbx+r12], 0");
+            }
+            Token::Sub(i) => {
+                add_mul(&mut out, i, 1, "sub");
+                inst(&mut out, "mov byte ptr [rbx+r12], 0");
+            }
+            Token::Dup(i1, mul1, i2, mul2) => {
+                add_mul(&mut out, i1, mul1 as u8, "add");
+                add_mul(&mut out, i1 + i2, mul2 as u8, "add");
+                inst(&mut out, "mov byte ptr [rbx+r12], 0");
+            }
+            Token::Scan(i) => {
+                labels += 1;
+
+                let _ = writeln!(out, ".Lscan{labels}:");
+                inst(
+                    &mut out,
+                    &format!("cmp byte ptr [rbx+r12], 0\nje .Lscanned{labels}"),
+                );
+                goto(&mut out, "r12", offset(i));
+                inst(&mut out, &format!("jmp .Lscan{labels}"));
+                let _ = writeln!(out, ".Lscanned{labels}:");
+            }
+
+            Token::End => {
+                labels += 1;
+
+                inst(
+                    &mut out,
+                    &format!("cmp byte ptr [rbx+r12], 0\nje .Lended{labels}"),
+                );
+                let _ = writeln!(out, ".Lhang{labels}:");
+                inst(
+                    &mut out,
+                    &format!("mov eax, 34\nsyscall\njmp .Lhang{labels}"),
+                );
+                let _ = writeln!(out, ".Lended{labels}:");
+            }
+
+            Token::Dump => {}
+        }
+    }
+
+    inst(&mut out, "mov eax, 60\nxor edi, edi\nsyscall");
+    out
+}
+
+/// Writes each line of `s` as an indented instruction.
+fn inst(out: &mut String, s: &str) {
+    for l in s.lines() {
+        let _ = writeln!(out, "    {l}");
+    }
+}
+
+/// Adds `off` (from [`offset`]) to `reg`, wrapping around the tape.
+fn goto(out: &mut String, reg: &str, off: usize) {
+    if off != 0 {
+        inst(
+            out,
+            &format!("add {reg}, {off}\ncmp {reg}, {TAPE_LEN}\njb 1f\nsub {reg}, {TAPE_LEN}"),
+        );
+        out.push_str("1:\n");
+    }
+}
+
+/// Emits the `syscall` for reading/writing one byte at the current cell.
+fn syscall_cell(out: &mut String) {
+    inst(out, "lea rsi, [rbx+r12]\nmov edx, 1\nsyscall");
+}
+
+/// Applies `op` to the cell at `i` and the current cell times `mul`.
+fn add_mul(out: &mut String, i: isize, mul: u8, op: &str) {
+    inst(out, "mov rcx, r12");
+    goto(out, "rcx", offset(i));
+    inst(out, "movzx eax, byte ptr [rbx+r12]");
+    if mul != 1 {
+        inst(out, &format!("imul eax, eax, {mul}"));
+    }
+    inst(out, &format!("{op} byte ptr [rbx+rcx], al"));
+}
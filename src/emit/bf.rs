@@ -0,0 +1,195 @@
+//! Minimized brain* backend.
+//!
+//! Writes the optimized program back out as canonical brain*, without
+//! comments, and with redundant code removed:
+//!
+//! - runs of `+`/`-` and `>`/`<` are cancelled out
+//! - loops that can never run (at the start of the program, or right after
+//!   something that leaves the current cell zero) are dropped
+//! - clearing a cell that is already zero is dropped
+//! - trailing code that can't affect the output is dropped
+
+use crate::token::Token;
+#[cfg(not(feature = "nowrap"))]
+use crate::Cell;
+
+/// Translates `code` into minimized brain*. If `width` is given, the output
+/// is wrapped to lines of at most that many characters. Each
+/// [`Dump`](Token::Dump) is written as `debug_char`, or dropped if there's
+/// none.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+pub fn emit(code: &[Token], width: Option<usize>, debug_char: Option<char>) -> String {
+    let mut out = Vec::new();
+
+    // Whether no cell has been written to yet, i.e. the whole tape is zero.
+    let mut pristine = true;
+    // Whether the current cell is known to be zero.
+    let mut zero = true;
+
+    let mut ip = 0;
+    while ip < code.len() {
+        let tok = code[ip];
+        ip += 1;
+
+        match tok {
+            Token::Dump if debug_char.is_none() => continue,
+            Token::LBrack(end) if zero => {
+                ip = end + 1;
+                continue;
+            }
+            Token::Zero
+            | Token::Add(..)
+            | Token::Sub(_)
+            | Token::Dup(..)
+            | Token::Scan(_)
+            | Token::End
+                if zero =>
+            {
+                continue;
+            }
+
+            Token::Set(i) if zero => {
+                push(&mut out, Token::Inc(i));
+                pristine = false;
+                zero = i == 0;
+                continue;
+            }
+
+            _ => {}
+        }
+
+        match tok {
+            Token::Goto(_) => zero = pristine,
+            Token::Inc(_) | Token::Dec(_) | Token::In | Token::LBrack(_) | Token::Set(_) => {
+                pristine = false;
+                zero = false;
+            }
+            Token::RBrack(_)
+            | Token::Zero
+            | Token::Add(..)
+            | Token::Sub(_)
+            | Token::Dup(..)
+            | Token::Scan(_)
+            | Token::End => zero = true,
+            _ => {}
+        }
+
+        push(&mut out, tok);
+    }
+
+    while matches!(
+        out.last(),
+        Some(
+            Token::Inc(_)
+                | Token::Dec(_)
+                | Token::Goto(_)
+                | Token::Zero
+                | Token::Set(_)
+                | Token::Add(..)
+                | Token::Sub(_)
+                | Token::Dup(..)
+        )
+    ) {
+        out.pop();
+    }
+
+    let text: String = out
+        .iter()
+        .map(|tok| match (tok, debug_char) {
+            (Token::Dump, Some(ch)) => ch.to_string(),
+            _ => tok.to_string(),
+        })
+        .collect();
+
+    match width {
+        Some(width) if width > 0 => {
+            let chars: Vec<char> = text.chars().collect();
+            let mut wrapped = String::with_capacity(text.len() + text.len() / width + 1);
+
+            for line in chars.chunks(width) {
+                wrapped.extend(line);
+                wrapped.push('\n');
+            }
+
+            wrapped
+        }
+        _ => text + "\n",
+    }
+}
+
+/// Pushes a token, merging it into the previous one where possible.
+fn push(out: &mut Vec<Token>, tok: Token) {
+    let merged = match (out.last(), tok) {
+        (Some(&Token::Goto(a)), Token::Goto(b)) => Some(Token::Goto(a + b)),
+
+        #[cfg(not(feature = "nowrap"))]
+        (Some(&(Token::Inc(_) | Token::Dec(_))), Token::Inc(_) | Token::Dec(_)) => {
+            Some(shortest(net(out[out.len() - 1]).wrapping_add(net(tok))))
+        }
+
+        #[cfg(feature = "nowrap")]
+        (Some(&Token::Inc(a)), Token::Inc(b)) => Some(Token::Inc(a.saturating_add(b))),
+        #[cfg(feature = "nowrap")]
+        (Some(&Token::Dec(a)), Token::Dec(b)) => Some(Token::Dec(a.saturating_add(b))),
+
+        _ => None,
+    };
+
+    match merged {
+        Some(Token::Goto(0) | Token::Inc(0) | Token::Dec(0)) => {
+            out.pop();
+        }
+        Some(tok) => *out.last_mut().expect("merged with the last token") = tok,
+        None => out.push(tok),
+    }
+}
+
+/// The net change an `Inc` or `Dec` makes to a cell.
+#[cfg(not(feature = "nowrap"))]
+fn net(tok: Token) -> Cell {
+    match tok {
+        Token::Inc(i) => i,
+        Token::Dec(i) => (0 as Cell).wrapping_sub(i),
+        _ => unreachable!("only called on `Inc` and `Dec`"),
+    }
+}
+
+/// Picks whichever of `Inc`/`Dec` takes fewer characters to change a cell by
+/// `n`.
+#[cfg(not(feature = "nowrap"))]
+fn shortest(n: Cell) -> Token {
+    let neg = (0 as Cell).wrapping_sub(n);
+
+    if magnitude(neg) < magnitude(n) {
+        Token::Dec(neg)
+    } else {
+        Token::Inc(n)
+    }
+}
+
+/// How many `+`s it takes to add `n` to a cell.
+#[cfg(not(feature = "nowrap"))]
+#[allow(clippy::unnecessary_cast)]
+fn magnitude(n: Cell) -> u64 {
+    n as u64 & (u64::MAX >> (64 - Cell::BITS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{optimize, parse_with, ParseOptions};
+
+    #[test]
+    fn dump_uses_the_debug_char() {
+        let options = ParseOptions {
+            debug_chars: vec![';'],
+            ..ParseOptions::default()
+        };
+        let code = optimize(&parse_with("+;>+.", &options));
+
+        assert_eq!(emit(&code, None, Some('#')), "+#>+.\n");
+        assert_eq!(emit(&code, None, None), "+>+.\n");
+    }
+}
@@ -0,0 +1,175 @@
+//! Minimal static ELF executable backend, for Linux x86-64.
+//!
+//! The executable has no dependencies: I/O goes straight through the `read`
+//! and `write` syscalls, one byte at a time. The code is mapped read-execute,
+//! and the tape lives in a separate zero-initialized read-write segment.
+
+use super::{x86, TAPE_LEN};
+use crate::token::Token;
+
+const CODE_ADDR: u64 = 0x400000;
+const PAGE_SIZE: u64 = 0x1000;
+
+const EHDR_SIZE: u16 = 64;
+const PHDR_SIZE: u16 = 56;
+const HEADERS_SIZE: u64 = EHDR_SIZE as u64 + 2 * PHDR_SIZE as u64;
+
+/// Translates `code` into a static ELF executable.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+pub fn emit(code: &[Token]) -> Vec<u8> {
+    let mut asm = Vec::new();
+
+    // mov rbx, tape_addr (filled in below, once the code's size is known)
+    asm.extend([0x48, 0xbb]);
+    asm.extend(0u64.to_le_bytes());
+    // xor r12d, r12d
+    asm.extend([0x45, 0x31, 0xe4]);
+
+    x86::assemble(code, &mut asm, |asm, hook| {
+        match hook {
+            x86::Hook::In => {
+                // xor eax, eax (SYS_read)
+                asm.extend([0x31, 0xc0]);
+                // xor edi, edi (stdin)
+                asm.extend([0x31, 0xff]);
+                syscall_cell(asm);
+                // test rax, rax
+                asm.extend([0x48, 0x85, 0xc0]);
+                // jg +5
+                asm.extend([0x7f, 0x05]);
+                x86::set_cell(asm, 0);
+            }
+            x86::Hook::Out => {
+                // mov eax, 1 (SYS_write)
+                asm.extend([0xb8, 1, 0, 0, 0]);
+                // mov edi, 1 (stdout)
+                asm.extend([0xbf, 1, 0, 0, 0]);
+                syscall_cell(asm);
+            }
+            x86::Hook::End => {
+                // mov eax, 34 (SYS_pause)
+                asm.extend([0xb8, 34, 0, 0, 0]);
+                // syscall
+                asm.extend([0x0f, 0x05]);
+                // jmp -9
+                asm.extend([0xeb, 0xf7]);
+            }
+            x86::Hook::Dump => {}
+        }
+
+        Some(())
+    })
+    .expect("brackets should be matched");
+
+    // mov eax, 60 (SYS_exit)
+    asm.extend([0xb8, 60, 0, 0, 0]);
+    // xor edi, edi
+    asm.extend([0x31, 0xff]);
+    // syscall
+    asm.extend([0x0f, 0x05]);
+
+    let file_size = HEADERS_SIZE + asm.len() as u64;
+
+    // The tape goes on the first page after the code, so they never overlap.
+    let tape_addr = (CODE_ADDR + file_size).next_multiple_of(PAGE_SIZE);
+    asm[2..10].copy_from_slice(&tape_addr.to_le_bytes());
+
+    let mut out = Vec::with_capacity(file_size as usize);
+
+    // e_ident: magic, 64-bit, little-endian, version 1, System V ABI
+    out.extend([0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    out.extend([0; 8]);
+    // e_type: executable
+    out.extend(2u16.to_le_bytes());
+    // e_machine: x86-64
+    out.extend(0x3eu16.to_le_bytes());
+    // e_version
+    out.extend(1u32.to_le_bytes());
+    // e_entry
+    out.extend((CODE_ADDR + HEADERS_SIZE).to_le_bytes());
+    // e_phoff
+    out.extend((EHDR_SIZE as u64).to_le_bytes());
+    // e_shoff
+    out.extend(0u64.to_le_bytes());
+    // e_flags
+    out.extend(0u32.to_le_bytes());
+    // e_ehsize, e_phentsize, e_phnum
+    out.extend(EHDR_SIZE.to_le_bytes());
+    out.extend(PHDR_SIZE.to_le_bytes());
+    out.extend(2u16.to_le_bytes());
+    // e_shentsize, e_shnum, e_shstrndx
+    out.extend([0; 6]);
+
+    // The code, including the headers: read + execute
+    program_header(&mut out, 5, CODE_ADDR, file_size, file_size);
+    // The tape: read + write, all zeroes
+    program_header(&mut out, 6, tape_addr, 0, TAPE_LEN as u64);
+
+    out.extend(asm);
+    out
+}
+
+/// Emits the `syscall` for reading/writing one byte at the current cell.
+fn syscall_cell(asm: &mut Vec<u8>) {
+    // lea rsi, [rbx+r12]
+    asm.extend([0x4a, 0x8d, 0x34, 0x23]);
+    // mov edx, 1
+    asm.extend([0xba, 1, 0, 0, 0]);
+    // syscall
+    asm.extend([0x0f, 0x05]);
+}
+
+/// Emits a `PT_LOAD` program header for a segment starting at file offset 0.
+fn program_header(out: &mut Vec<u8>, flags: u32, addr: u64, file_size: u64, mem_size: u64) {
+    // p_type: PT_LOAD
+    out.extend(1u32.to_le_bytes());
+    out.extend(flags.to_le_bytes());
+    // p_offset
+    out.extend(0u64.to_le_bytes());
+    // p_vaddr, p_paddr
+    out.extend(addr.to_le_bytes());
+    out.extend(addr.to_le_bytes());
+    out.extend(file_size.to_le_bytes());
+    out.extend(mem_size.to_le_bytes());
+    // p_align
+    out.extend(PAGE_SIZE.to_le_bytes());
+}
+
+#[cfg(all(test, target_os = "linux", target_arch = "x86_64"))]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    use super::*;
+    use crate::program::Program;
+
+    /// Builds `source` into an executable, runs it, and returns its output.
+    fn run(name: &str, source: String) -> Vec<u8> {
+        let program = Program::new(source);
+        let path = std::env::temp_dir().join(format!("brim-elf-{name}-{}", std::process::id()));
+
+        fs::write(&path, emit(&program.code)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        let out = Command::new(&path).output().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(out.status.success(), "`{name}` exited with {}", out.status);
+        out.stdout
+    }
+
+    #[test]
+    fn hello() {
+        let source = "++++++++[>++++++++<-]>+.".to_string();
+        assert_eq!(run("hello", source), b"A");
+    }
+
+    #[test]
+    fn code_bigger_than_2_mib() {
+        // The code runs well past where the tape used to be mapped.
+        let source = "+".repeat(65) + &"[->+<]>".repeat(50000) + ".";
+        assert_eq!(run("big", source), b"A");
+    }
+}
@@ -0,0 +1,60 @@
+//! Human-readable dump of the optimized tokens.
+//!
+//! Each token is printed on its own line, with its index, a mnemonic (see
+//! [`mnemonic`]), and indented by how deeply nested in loops it is.
+
+use std::fmt::Write;
+
+use crate::token::Token;
+
+/// Lists `code`, one token per line.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+pub fn emit(code: &[Token]) -> String {
+    let mut out = String::new();
+    let digits = code.len().saturating_sub(1).to_string().len();
+    let mut depth = 0usize;
+
+    for (i, tok) in code.iter().enumerate() {
+        if matches!(tok, Token::RBrack(_)) {
+            depth = depth.saturating_sub(1);
+        }
+
+        let _ = writeln!(out, "{i:>digits$}  {:2$}{}", "", mnemonic(tok), depth * 2);
+
+        if matches!(tok, Token::LBrack(_)) {
+            depth += 1;
+        }
+    }
+
+    out
+}
+
+/// Describes a token in a compact form, e.g. `add [+3] *2`, `scan -1`, or
+/// `jz -> 42`. Offsets are always relative to the current cell, and signed.
+#[allow(clippy::unnecessary_cast)]
+pub fn mnemonic(tok: &Token) -> String {
+    match *tok {
+        Token::Inc(i) => format!("inc {i}"),
+        Token::Dec(i) => format!("dec {i}"),
+        Token::Goto(i) => format!("move {i:+}"),
+        Token::LBrack(i) => format!("jz -> {i}"),
+        Token::RBrack(i) => format!("jnz -> {i}"),
+        Token::Out => "out".to_string(),
+        Token::In => "in".to_string(),
+
+        Token::Zero => "zero".to_string(),
+        Token::Set(i) => format!("set {i}"),
+        Token::Add(i, mul) => format!("add [{i:+}] *{mul}"),
+        Token::Sub(i) => format!("sub [{i:+}]"),
+        Token::Dup(i1, mul1, i2, mul2) => {
+            format!("dup [{i1:+}] *{mul1}, [{:+}] *{mul2}", i1 + i2)
+        }
+        Token::Scan(i) => format!("scan {i:+}"),
+
+        Token::End => "end".to_string(),
+
+        Token::Dump => "dump".to_string(),
+    }
+}
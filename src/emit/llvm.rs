@@ -0,0 +1,229 @@
+//! LLVM IR text backend.
+//!
+//! The emitted module defines `main`, keeps the tape in the global `@tape`,
+//! and uses libc's `getchar`/`putchar` for I/O. Each loop becomes a header,
+//! body, and exit block. Pointers are opaque (`ptr`), so LLVM 15 or newer is
+//! expected, e.g. `clang -O3 prog.ll -o prog`.
+
+use std::fmt::Write;
+
+use super::{offset, TAPE_LEN};
+use crate::token::Token;
+
+/// Translates `code` into a textual LLVM IR module.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+#[allow(clippy::unnecessary_cast)]
+pub fn emit(code: &[Token]) -> String {
+    let mut b = Builder::default();
+
+    let _ = writeln!(
+        b.out,
+        "@tape = internal global [{TAPE_LEN} x i8] zeroinitializer\n\n\
+         declare i32 @getchar()\n\
+         declare i32 @putchar(i32)\n\n\
+         define i32 @main() {{\n\
+         entry:\n  %sp = alloca i64\n  store i64 0, ptr %sp"
+    );
+
+    let mut loops = Vec::new();
+
+    for tok in code {
+        match *tok {
+            Token::Inc(i) => b.add_cell(i as u8),
+            Token::Dec(i) => b.add_cell((i as u8).wrapping_neg()),
+            Token::Goto(i) => {
+                let sp = b.sp();
+                let sp = b.wrap(&sp, offset(i));
+                b.inst(format!("store i64 {sp}, ptr %sp"));
+            }
+
+            Token::In => {
+                let c = b.tmp();
+                b.inst(format!("{c} = call i32 @getchar()"));
+                let eof = b.tmp();
+                b.inst(format!("{eof} = icmp slt i32 {c}, 0"));
+                let v = b.tmp();
+                b.inst(format!("{v} = select i1 {eof}, i32 0, i32 {c}"));
+                let byte = b.tmp();
+                b.inst(format!("{byte} = trunc i32 {v} to i8"));
+                let ptr = b.cell(0);
+                b.inst(format!("store i8 {byte}, ptr {ptr}"));
+            }
+            Token::Out => {
+                let ptr = b.cell(0);
+                let v = b.tmp();
+                b.inst(format!("{v} = load i8, ptr {ptr}"));
+                let c = b.tmp();
+                b.inst(format!("{c} = zext i8 {v} to i32"));
+                b.inst(format!("call i32 @putchar(i32 {c})"));
+            }
+
+            Token::LBrack(_) => {
+                let id = b.label();
+                loops.push(id);
+
+                b.inst(format!("br label %loop{id}.head"));
+                b.block(format!("loop{id}.head"));
+                b.branch_nonzero(&format!("loop{id}.body"), &format!("loop{id}.exit"));
+                b.block(format!("loop{id}.body"));
+            }
+            Token::RBrack(_) => {
+                let id = loops.pop().expect("brackets should be matched");
+
+                b.inst(format!("br label %loop{id}.head"));
+                b.block(format!("loop{id}.exit"));
+            }
+
+            Token::Zero => b.set_cell(0),
+            Token::Set(i) => b.set_cell(i as u8),
+            Token::Add(i, mul) => {
+                b.add_mul(i, mul as u8, "add");
+                b.set_cell(0);
+            }
+            Token::Sub(i) => {
+                b.add_mul(i, 1, "sub");
+                b.set_cell(0);
+            }
+            Token::Dup(i1, mul1, i2, mul2) => {
+                b.add_mul(i1, mul1 as u8, "add");
+                b.add_mul(i1 + i2, mul2 as u8, "add");
+                b.set_cell(0);
+            }
+            Token::Scan(i) => {
+                let id = b.label();
+
+                b.inst(format!("br label %scan{id}.head"));
+                b.block(format!("scan{id}.head"));
+                b.branch_nonzero(&format!("scan{id}.body"), &format!("scan{id}.exit"));
+                b.block(format!("scan{id}.body"));
+                let sp = b.sp();
+                let sp = b.wrap(&sp, offset(i));
+                b.inst(format!("store i64 {sp}, ptr %sp"));
+                b.inst(format!("br label %scan{id}.head"));
+                b.block(format!("scan{id}.exit"));
+            }
+
+            Token::End => {
+                let id = b.label();
+
+                b.branch_nonzero(&format!("end{id}.hang"), &format!("end{id}.exit"));
+                b.block(format!("end{id}.hang"));
+                b.inst(format!("br label %end{id}.hang"));
+                b.block(format!("end{id}.exit"));
+            }
+
+            Token::Dump => {}
+        }
+    }
+
+    b.inst("ret i32 0".to_string());
+    b.out.push_str("}\n");
+    b.out
+}
+
+/// Tracks the function body being built, as well as fresh names.
+#[derive(Default)]
+struct Builder {
+    out: String,
+    tmps: usize,
+    labels: usize,
+}
+
+impl Builder {
+    fn tmp(&mut self) -> String {
+        self.tmps += 1;
+        format!("%t{}", self.tmps)
+    }
+
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels
+    }
+
+    fn inst(&mut self, inst: String) {
+        let _ = writeln!(self.out, "  {inst}");
+    }
+
+    fn block(&mut self, name: String) {
+        let _ = writeln!(self.out, "\n{name}:");
+    }
+
+    /// Loads the tape pointer.
+    fn sp(&mut self) -> String {
+        let sp = self.tmp();
+        self.inst(format!("{sp} = load i64, ptr %sp"));
+        sp
+    }
+
+    /// Adds `off` (from [`offset`]) to `x`, wrapping around the tape.
+    fn wrap(&mut self, x: &str, off: usize) -> String {
+        if off == 0 {
+            return x.to_string();
+        }
+
+        let sum = self.tmp();
+        self.inst(format!("{sum} = add i64 {x}, {off}"));
+        let over = self.tmp();
+        self.inst(format!("{over} = icmp uge i64 {sum}, {TAPE_LEN}"));
+        let wrapped = self.tmp();
+        self.inst(format!("{wrapped} = sub i64 {sum}, {TAPE_LEN}"));
+        let res = self.tmp();
+        self.inst(format!(
+            "{res} = select i1 {over}, i64 {wrapped}, i64 {sum}"
+        ));
+        res
+    }
+
+    /// Gets a pointer to the cell at `sp + off`.
+    fn cell(&mut self, off: usize) -> String {
+        let sp = self.sp();
+        let idx = self.wrap(&sp, off);
+        let ptr = self.tmp();
+        self.inst(format!(
+            "{ptr} = getelementptr inbounds [{TAPE_LEN} x i8], ptr @tape, i64 0, i64 {idx}"
+        ));
+        ptr
+    }
+
+    fn add_cell(&mut self, i: u8) {
+        let ptr = self.cell(0);
+        let v = self.tmp();
+        self.inst(format!("{v} = load i8, ptr {ptr}"));
+        let res = self.tmp();
+        self.inst(format!("{res} = add i8 {v}, {i}"));
+        self.inst(format!("store i8 {res}, ptr {ptr}"));
+    }
+
+    fn set_cell(&mut self, i: u8) {
+        let ptr = self.cell(0);
+        self.inst(format!("store i8 {i}, ptr {ptr}"));
+    }
+
+    /// Applies `op` to the cell at `i` and the current cell times `mul`.
+    fn add_mul(&mut self, i: isize, mul: u8, op: &str) {
+        let cur = self.cell(0);
+        let v = self.tmp();
+        self.inst(format!("{v} = load i8, ptr {cur}"));
+        let prod = self.tmp();
+        self.inst(format!("{prod} = mul i8 {v}, {mul}"));
+
+        let ptr = self.cell(offset(i));
+        let t = self.tmp();
+        self.inst(format!("{t} = load i8, ptr {ptr}"));
+        let res = self.tmp();
+        self.inst(format!("{res} = {op} i8 {t}, {prod}"));
+        self.inst(format!("store i8 {res}, ptr {ptr}"));
+    }
+
+    /// Branches to `then` if the current cell is nonzero, else to `els`.
+    fn branch_nonzero(&mut self, then: &str, els: &str) {
+        let ptr = self.cell(0);
+        let v = self.tmp();
+        self.inst(format!("{v} = load i8, ptr {ptr}"));
+        let c = self.tmp();
+        self.inst(format!("{c} = icmp ne i8 {v}, 0"));
+        self.inst(format!("br i1 {c}, label %{then}, label %{els}"));
+    }
+}
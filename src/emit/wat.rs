@@ -0,0 +1,245 @@
+//! WebAssembly text backend.
+//!
+//! The emitted module keeps the tape at the start of its exported linear
+//! `memory`, and exports a single function `run`. I/O goes through two
+//! imports:
+//!
+//! - `env.read: () -> i32`, which should return the next input byte (or 0)
+//! - `env.write: (i32) -> ()`, which receives each output byte
+
+use std::fmt::Write;
+
+use super::{offset, TAPE_LEN};
+use crate::token::Token;
+
+/// Translates `code` into a WebAssembly text module.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+#[allow(clippy::unnecessary_cast)]
+pub fn emit(code: &[Token]) -> String {
+    let mut out = String::new();
+    let mut depth = 2;
+
+    out.push_str("(module\n");
+    out.push_str("  (import \"env\" \"read\" (func $read (result i32)))\n");
+    out.push_str("  (import \"env\" \"write\" (func $write (param i32)))\n");
+    out.push_str("  (memory (export \"memory\") 1)\n\n");
+
+    // Wraps an address in `0..2*TAPE_LEN` around the tape.
+    out.push_str("  (func $wrap (param $x i32) (result i32)\n");
+    let _ = writeln!(
+        out,
+        "    local.get $x\n    i32.const {TAPE_LEN}\n    i32.sub"
+    );
+    let _ = writeln!(
+        out,
+        "    local.get $x\n    local.get $x\n    i32.const {TAPE_LEN}"
+    );
+    out.push_str("    i32.ge_u\n    select)\n\n");
+
+    out.push_str("  (func (export \"run\") (local $sp i32) (local $t i32)\n");
+
+    for tok in code {
+        match *tok {
+            Token::Inc(i) => add_cell(&mut out, depth, i as u8),
+            Token::Dec(i) => add_cell(&mut out, depth, (i as u8).wrapping_neg()),
+            Token::Goto(i) => goto(&mut out, depth, i),
+
+            Token::In => line(&mut out, depth, "local.get $sp\ncall $read\ni32.store8"),
+            Token::Out => line(&mut out, depth, "local.get $sp\ni32.load8_u\ncall $write"),
+
+            Token::LBrack(_) => {
+                line(&mut out, depth, "block");
+                line(
+                    &mut out,
+                    depth + 1,
+                    "local.get $sp\ni32.load8_u\ni32.eqz\nbr_if 0",
+                );
+                line(&mut out, depth + 1, "loop");
+                depth += 2;
+            }
+            Token::RBrack(_) => {
+                line(&mut out, depth, "local.get $sp\ni32.load8_u\nbr_if 0");
+                depth -= 2;
+                line(&mut out, depth + 1, "end");
+                line(&mut out, depth, "end");
+            }
+
+            Token::Zero => set_cell(&mut out, depth, 0),
+            Token::Set(i) => set_cell(&mut out, depth, i as u8),
+            Token::Add(i, mul) => {
+                add_mul(&mut out, depth, i, mul as u8, "i32.add");
+                set_cell(&mut out, depth, 0);
+            }
+            Token::Sub(i) => {
+                add_mul(&mut out, depth, i, 1, "i32.sub");
+                set_cell(&mut out, depth, 0);
+            }
+            Token::Dup(i1, mul1, i2, mul2) => {
+                add_mul(&mut out, depth, i1, mul1 as u8, "i32.add");
+                add_mul(&mut out, depth, i1 + i2, mul2 as u8, "i32.add");
+                set_cell(&mut out, depth, 0);
+            }
+            Token::Scan(i) => {
+                line(&mut out, depth, "block");
+                line(&mut out, depth + 1, "loop");
+                line(
+                    &mut out,
+                    depth + 2,
+                    "local.get $sp\ni32.load8_u\ni32.eqz\nbr_if 1",
+                );
+                goto(&mut out, depth + 2, i);
+                line(&mut out, depth + 2, "br 0");
+                line(&mut out, depth + 1, "end");
+                line(&mut out, depth, "end");
+            }
+
+            Token::End => {
+                line(&mut out, depth, "local.get $sp\ni32.load8_u\nif");
+                line(&mut out, depth + 1, "loop\n  br 0\nend");
+                line(&mut out, depth, "end");
+            }
+
+            Token::Dump => {}
+        }
+    }
+
+    out.push_str("  )\n)\n");
+    out
+}
+
+/// Writes each line of `s`, indented by `depth` levels.
+fn line(out: &mut String, depth: usize, s: &str) {
+    for l in s.lines() {
+        let _ = writeln!(out, "{:1$}{l}", "", depth * 2);
+    }
+}
+
+fn add_cell(out: &mut String, depth: usize, i: u8) {
+    line(
+        out,
+        depth,
+        &format!("local.get $sp\nlocal.get $sp\ni32.load8_u\ni32.const {i}\ni32.add\ni32.store8"),
+    );
+}
+
+fn set_cell(out: &mut String, depth: usize, i: u8) {
+    line(
+        out,
+        depth,
+        &format!("local.get $sp\ni32.const {i}\ni32.store8"),
+    );
+}
+
+fn goto(out: &mut String, depth: usize, i: isize) {
+    let off = offset(i);
+    if off != 0 {
+        line(
+            out,
+            depth,
+            &format!("local.get $sp\ni32.const {off}\ni32.add\ncall $wrap\nlocal.set $sp"),
+        );
+    }
+}
+
+/// Applies `op` to the cell at `i` and the current cell times `mul`.
+fn add_mul(out: &mut String, depth: usize, i: isize, mul: u8, op: &str) {
+    line(
+        out,
+        depth,
+        &format!(
+            "local.get $sp\ni32.const {}\ni32.add\ncall $wrap\nlocal.tee $t\n\
+             local.get $t\ni32.load8_u\n\
+             local.get $sp\ni32.load8_u\ni32.const {mul}\ni32.mul\n\
+             {op}\ni32.store8",
+            offset(i),
+        ),
+    );
+}
+
+// The module always has the default tape and cells, so it only matches the
+// interpreter in builds that do too.
+#[cfg(all(
+    test,
+    not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+))]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::machine::Machine;
+    use crate::program::Program;
+
+    /// The module's input left to read, and its output so far.
+    type Io = (VecDeque<u8>, Vec<u8>);
+
+    /// Runs `source` both as a WebAssembly module and interpreted, checking
+    /// that they give the same output and leave the same tape behind.
+    fn check(source: &str, input: &[u8]) {
+        let program = Program::new(source.to_string());
+
+        let mut machine = Machine::new(&program.code);
+        let mut expected = Vec::new();
+        machine.run(&mut input.iter().copied(), &mut expected);
+
+        let wasm = wat::parse_str(emit(&program.code)).expect("invalid module");
+
+        let engine = wasmi::Engine::default();
+        let module = wasmi::Module::new(&engine, &wasm[..]).unwrap();
+        let mut store = wasmi::Store::new(&engine, (input.iter().copied().collect(), Vec::new()));
+        let mut linker = wasmi::Linker::<Io>::new(&engine);
+        linker
+            .func_wrap("env", "read", |mut caller: wasmi::Caller<Io>| {
+                i32::from(caller.data_mut().0.pop_front().unwrap_or(0))
+            })
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "write",
+                |mut caller: wasmi::Caller<Io>, byte: i32| {
+                    caller.data_mut().1.push(byte as u8);
+                },
+            )
+            .unwrap();
+
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|pre| pre.start(&mut store))
+            .unwrap();
+        instance
+            .get_typed_func::<(), ()>(&store, "run")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        let tape = memory.data(&store)[..TAPE_LEN].to_vec();
+        let (_, output) = store.into_data();
+
+        #[allow(clippy::unnecessary_cast)]
+        let expected_tape: Vec<u8> = machine.tape.iter().map(|&c| c as u8).collect();
+
+        assert_eq!(output, expected, "output of `{source}`");
+        assert_eq!(tape, expected_tape, "tape of `{source}`");
+    }
+
+    #[test]
+    fn hello_world() {
+        check(
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.",
+            b"",
+        );
+    }
+
+    #[test]
+    fn echo() {
+        check(",[.,]", b"hello");
+    }
+
+    #[test]
+    fn wraparound_and_offsets() {
+        check("-.<+++.>>+++[->+>++<<]>>>+++[-<<+<+++>>>]<[<]", b"");
+    }
+}
@@ -0,0 +1,196 @@
+//! x86-64 machine code generation, shared by the ELF backend and the JIT.
+//!
+//! Register usage:
+//!
+//! - `rbx`: tape base
+//! - `r12`: tape pointer (an index into the tape)
+//!
+//! Everything else is free for the caller's [`Hook`]s, except `rax` and
+//! `rcx`, which are clobbered by the generated code.
+
+use super::{offset, TAPE_LEN};
+use crate::token::Token;
+
+/// An operation the caller provides the machine code for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    /// Read a byte into the current cell, storing 0 on EOF.
+    In,
+    /// Write the current cell.
+    Out,
+    /// Hang forever. Only reached if the current cell is nonzero.
+    End,
+    /// A debug dump.
+    Dump,
+}
+
+/// Appends machine code for `code` to `asm`, calling `hook` to emit each
+/// [`Hook`]. If `hook` returns `None`, so does this.
+///
+/// `rbx` and `r12` must already be set up (see the [module docs](self)).
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+#[allow(clippy::unnecessary_cast)]
+pub fn assemble(
+    code: &[Token],
+    asm: &mut Vec<u8>,
+    mut hook: impl FnMut(&mut Vec<u8>, Hook) -> Option<()>,
+) -> Option<()> {
+    let mut lbracks = Vec::new();
+
+    for tok in code {
+        match *tok {
+            Token::Inc(i) => add_cell(asm, i as u8),
+            Token::Dec(i) => add_cell(asm, (i as u8).wrapping_neg()),
+            Token::Goto(i) => goto(asm, i),
+
+            Token::In => hook(asm, Hook::In)?,
+            Token::Out => hook(asm, Hook::Out)?,
+
+            Token::LBrack(_) => {
+                cmp_zero(asm);
+                // je rel32
+                asm.extend([0x0f, 0x84, 0, 0, 0, 0]);
+                lbracks.push(asm.len());
+            }
+            Token::RBrack(_) => {
+                let lb = lbracks.pop()?;
+
+                cmp_zero(asm);
+                // jne rel32
+                asm.extend([0x0f, 0x85]);
+                let rel = lb as i32 - (asm.len() + 4) as i32;
+                asm.extend(rel.to_le_bytes());
+
+                let rel = asm.len() as i32 - lb as i32;
+                asm[lb - 4..lb].copy_from_slice(&rel.to_le_bytes());
+            }
+
+            Token::Zero => set_cell(asm, 0),
+            Token::Set(i) => set_cell(asm, i as u8),
+            Token::Add(i, mul) => {
+                target(asm, i);
+                load_mul(asm, mul as u8);
+                // add byte [rbx+rcx], al
+                asm.extend([0x00, 0x04, 0x0b]);
+                set_cell(asm, 0);
+            }
+            Token::Sub(i) => {
+                target(asm, i);
+                load_mul(asm, 1);
+                // sub byte [rbx+rcx], al
+                asm.extend([0x28, 0x04, 0x0b]);
+                set_cell(asm, 0);
+            }
+            Token::Dup(i1, mul1, i2, mul2) => {
+                target(asm, i1);
+                load_mul(asm, mul1 as u8);
+                // add byte [rbx+rcx], al
+                asm.extend([0x00, 0x04, 0x0b]);
+
+                target(asm, i1 + i2);
+                load_mul(asm, mul2 as u8);
+                // add byte [rbx+rcx], al
+                asm.extend([0x00, 0x04, 0x0b]);
+
+                set_cell(asm, 0);
+            }
+            Token::Scan(i) => {
+                let start = asm.len();
+                cmp_zero(asm);
+                // je rel8 (patched below)
+                asm.extend([0x74, 0]);
+                let je = asm.len();
+
+                goto(asm, i);
+                // jmp rel32
+                asm.push(0xe9);
+                let rel = start as i32 - (asm.len() + 4) as i32;
+                asm.extend(rel.to_le_bytes());
+
+                asm[je - 1] = (asm.len() - je) as u8;
+            }
+
+            Token::End => {
+                cmp_zero(asm);
+                // je rel32 (patched below)
+                asm.extend([0x0f, 0x84, 0, 0, 0, 0]);
+                let je = asm.len();
+
+                hook(asm, Hook::End)?;
+
+                let rel = (asm.len() - je) as i32;
+                asm[je - 4..je].copy_from_slice(&rel.to_le_bytes());
+            }
+
+            Token::Dump => hook(asm, Hook::Dump)?,
+        }
+    }
+
+    lbracks.is_empty().then_some(())
+}
+
+/// `add byte [rbx+r12], i`
+fn add_cell(asm: &mut Vec<u8>, i: u8) {
+    asm.extend([0x42, 0x80, 0x04, 0x23, i]);
+}
+
+/// `mov byte [rbx+r12], i`
+pub fn set_cell(asm: &mut Vec<u8>, i: u8) {
+    asm.extend([0x42, 0xc6, 0x04, 0x23, i]);
+}
+
+/// `cmp byte [rbx+r12], 0`
+fn cmp_zero(asm: &mut Vec<u8>) {
+    asm.extend([0x42, 0x80, 0x3c, 0x23, 0x00]);
+}
+
+/// Moves `r12` by `i`, wrapping around the tape.
+fn goto(asm: &mut Vec<u8>, i: isize) {
+    let off = offset(i);
+    if off == 0 {
+        return;
+    }
+
+    // add r12, off
+    asm.extend([0x49, 0x81, 0xc4]);
+    asm.extend((off as i32).to_le_bytes());
+    // cmp r12, TAPE_LEN
+    asm.extend([0x49, 0x81, 0xfc]);
+    asm.extend((TAPE_LEN as i32).to_le_bytes());
+    // jb +7
+    asm.extend([0x72, 0x07]);
+    // sub r12, TAPE_LEN
+    asm.extend([0x49, 0x81, 0xec]);
+    asm.extend((TAPE_LEN as i32).to_le_bytes());
+}
+
+/// Sets `rcx` to the (wrapped) index `r12 + i`.
+fn target(asm: &mut Vec<u8>, i: isize) {
+    // mov rcx, r12
+    asm.extend([0x4c, 0x89, 0xe1]);
+    // add rcx, off
+    asm.extend([0x48, 0x81, 0xc1]);
+    asm.extend((offset(i) as i32).to_le_bytes());
+    // cmp rcx, TAPE_LEN
+    asm.extend([0x48, 0x81, 0xf9]);
+    asm.extend((TAPE_LEN as i32).to_le_bytes());
+    // jb +7
+    asm.extend([0x72, 0x07]);
+    // sub rcx, TAPE_LEN
+    asm.extend([0x48, 0x81, 0xe9]);
+    asm.extend((TAPE_LEN as i32).to_le_bytes());
+}
+
+/// Sets `al` to the current cell times `mul`.
+fn load_mul(asm: &mut Vec<u8>, mul: u8) {
+    // movzx eax, byte [rbx+r12]
+    asm.extend([0x42, 0x0f, 0xb6, 0x04, 0x23]);
+
+    if mul != 1 {
+        // imul eax, eax, mul
+        asm.extend([0x69, 0xc0]);
+        asm.extend((mul as i32).to_le_bytes());
+    }
+}
@@ -0,0 +1,112 @@
+//! Golden-output tests: programs checked against the output they should give.
+//!
+//! A test is a program `foo.bf` with its expected output beside it in
+//! `foo.out`, and optionally its input in `foo.in`.
+
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A single golden-output test.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    /// The program to run.
+    pub program: PathBuf,
+    /// The program's input, if any.
+    pub input: Option<PathBuf>,
+    /// The program's expected output.
+    pub output: PathBuf,
+}
+
+/// Finds every test in `dir` and its subdirectories, sorted by path. Programs
+/// without an expected output are skipped.
+pub fn find(dir: &Path) -> io::Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let output = path.with_extension("out");
+            if path.extension().is_some_and(|ext| ext == "bf") && output.is_file() {
+                let input = path.with_extension("in");
+
+                cases.push(Case {
+                    input: input.is_file().then_some(input),
+                    program: path,
+                    output,
+                });
+            }
+        }
+    }
+
+    cases.sort_by(|a, b| a.program.cmp(&b.program));
+    Ok(cases)
+}
+
+/// Describes where `actual` output first differs from what was `expected`,
+/// showing the bytes around it. Returns `None` if they're the same.
+pub fn diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+    const CONTEXT: usize = 16;
+
+    let at = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(actual.len()));
+
+    if at == expected.len() && at == actual.len() {
+        return None;
+    }
+
+    let line_start = expected[..at]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let line = expected[..line_start]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+        + 1;
+    let col = at - line_start + 1;
+
+    let mut out = if at == actual.len() {
+        format!(
+            "output ends early at byte {at} (line {line}, column {col}), {} bytes short\n",
+            expected.len() - at
+        )
+    } else if at == expected.len() {
+        format!(
+            "output has {} extra bytes from byte {at} (line {line}, column {col})\n",
+            actual.len() - at
+        )
+    } else {
+        format!("output differs at byte {at} (line {line}, column {col})\n")
+    };
+
+    // Both sides share everything before `at`, so they line up to there.
+    let start = at.saturating_sub(CONTEXT);
+    let show = |bytes: &[u8]| {
+        let end = (at + CONTEXT).min(bytes.len());
+        format!(
+            "{}\"{}\"{}",
+            if start > 0 { "..." } else { "" },
+            bytes[start..end].escape_ascii(),
+            if end < bytes.len() { "..." } else { "" },
+        )
+    };
+    let marker = (start > 0) as usize * 3 + 1 + expected[start..at].escape_ascii().count();
+
+    let _ = writeln!(out, "    expected: {}", show(expected));
+    let _ = writeln!(out, "    actual:   {}", show(actual));
+    let _ = writeln!(out, "              {}^", " ".repeat(marker));
+
+    Some(out)
+}
@@ -4,10 +4,35 @@ use std::process::exit;
 
 use crate::{Cell, CellMod};
 
+/// The kinds of failure brim exits on, each with its own exit code so that
+/// scripts can tell them apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Anything else, such as invalid arguments.
+    Other = 1,
+    /// Invalid source code, bytecode, or tape file.
+    Parse = 2,
+    /// Failing to read or write, e.g. a file or the program's output.
+    Io = 3,
+    /// The program ran too many steps.
+    StepLimit = 4,
+    /// The program ran for too long.
+    Timeout = 5,
+    /// The starting tape, or tape pointer, doesn't fit on the tape. Moving off
+    /// the tape while running isn't a failure, since the tape wraps around (or
+    /// grows, or stops at cell 0) instead.
+    TapeOverflow = 6,
+}
+
 /// Prints to stderr (in the format "msg: e") in red, then exits.
 pub fn err(msg: impl Display, e: impl Display) -> ! {
+    fail(Failure::Other, msg, e)
+}
+
+/// Like [`err`], but exits with the exit code for `failure`.
+pub fn fail(failure: Failure, msg: impl Display, e: impl Display) -> ! {
     eprintln!("{0}[38:5:1m{1}: {2}{0}[0m", 27 as char, msg, e);
-    exit(1);
+    exit(failure as i32);
 }
 
 /// Prints a warning to stderr in yellow.
@@ -55,3 +80,47 @@ pub fn left_right(i: isize) -> String {
         Ordering::Equal => String::new(),
     }
 }
+
+/// Finds the 1-based line and column (in characters) of a byte offset into
+/// `source`.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+
+    (line, col)
+}
+
+/// Finds the byte offset of a 1-based line and column (in characters) in
+/// `source`, if it exists.
+pub fn offset_of(source: &str, line: usize, col: usize) -> Option<usize> {
+    let start = if line == 1 {
+        0
+    } else {
+        source.match_indices('\n').nth(line.checked_sub(2)?)?.0 + 1
+    };
+
+    let text = source[start..].split('\n').next()?;
+    let (i, _) = text
+        .char_indices()
+        .chain([(text.len(), ' ')])
+        .nth(col.checked_sub(1)?)?;
+
+    Some(start + i)
+}
+
+/// Parses `n` or `start..end` into a range of cells.
+pub fn parse_range(s: &str) -> Option<(usize, usize)> {
+    match s.split_once("..") {
+        Some((start, end)) => {
+            let start = start.parse().ok()?;
+            let end = end.parse().ok()?;
+
+            (start < end).then_some((start, end))
+        }
+        None => {
+            let n = s.parse::<usize>().ok()?;
+            Some((n, n.checked_add(1)?))
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Execution history, for stepping a [`Machine`] backwards.
+//!
+//! Every step records the tape pointer and the old value of each cell it
+//! writes. To keep memory bounded, that undo log is cut into segments: every
+//! so often the whole machine is snapshotted and the log starts afresh. Going
+//! back past the start of a segment restores the previous snapshot and quietly
+//! re-runs the program up to that point, rebuilding its log.
+//!
+//! Input is recorded too, so re-running (whether to rebuild a segment, or
+//! stepping forwards again) reads the same bytes as the first time. Output is
+//! not undone.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::machine::{Machine, Observer, State};
+use crate::token::Token;
+use crate::Cell;
+
+/// How many steps to record between snapshots by default.
+pub const INTERVAL: usize = 4096;
+/// How many snapshots to keep by default.
+pub const SNAPSHOTS: usize = 256;
+
+/// A copy of a machine's state.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    tape: Vec<Cell>,
+    sp: usize,
+    ip: usize,
+    steps: u64,
+    inputs: usize,
+}
+
+/// How to undo a single step.
+#[derive(Clone, Copy, Debug, Default)]
+struct Undo {
+    sp: usize,
+    ip: usize,
+    cells: [(usize, Cell); 3],
+    len: usize,
+}
+
+/// Records what each step changes, and snapshots the machine periodically.
+#[derive(Clone, Debug)]
+struct Recorder {
+    interval: usize,
+    limit: usize,
+    snapshots: VecDeque<Snapshot>,
+    undo: Vec<Undo>,
+    pending: Undo,
+    inputs: usize,
+}
+
+impl Recorder {
+    fn snapshot(&mut self, machine: &Machine) {
+        if self.snapshots.len() == self.limit {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back(Snapshot {
+            tape: machine.tape.clone(),
+            sp: machine.sp,
+            ip: machine.ip,
+            steps: machine.steps,
+            inputs: self.inputs,
+        });
+        self.undo.clear();
+    }
+}
+
+impl Observer for Recorder {
+    fn before(&mut self, machine: &Machine) -> bool {
+        if self.undo.len() >= self.interval {
+            self.snapshot(machine);
+        }
+
+        self.pending = Undo {
+            sp: machine.sp,
+            ip: machine.ip,
+            ..Undo::default()
+        };
+
+        for cell in machine.writes() {
+            self.pending.cells[self.pending.len] = (cell, machine.cell(cell));
+            self.pending.len += 1;
+        }
+
+        true
+    }
+
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        if machine.code[ip] == Token::In {
+            self.inputs += 1;
+        }
+
+        self.undo.push(self.pending);
+        true
+    }
+}
+
+/// Reads from the input log where possible, and from `stdin` (recording what
+/// it reads) once it runs out.
+struct Replay<'a, I> {
+    log: &'a mut VecDeque<Option<u8>>,
+    pos: usize,
+    stdin: &'a mut I,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Replay<'_, I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = match self.log.get(self.pos) {
+            Some(&byte) => byte,
+            None => {
+                let byte = self.stdin.next();
+                self.log.push_back(byte);
+                byte
+            }
+        };
+
+        self.pos += 1;
+        byte
+    }
+}
+
+/// The recorded history of a [`Machine`].
+#[derive(Clone, Debug)]
+pub struct History {
+    recorder: Recorder,
+    /// Every read from the input since the oldest snapshot; `None` for EOF.
+    input: VecDeque<Option<u8>>,
+    /// How many reads happened before the start of `input`.
+    input_base: usize,
+}
+
+impl History {
+    /// Starts recording from the current state of `machine`, with the default
+    /// [`INTERVAL`] and number of [`SNAPSHOTS`].
+    pub fn new(machine: &Machine) -> Self {
+        Self::with_limits(machine, INTERVAL, SNAPSHOTS)
+    }
+
+    /// Starts recording from the current state of `machine`, snapshotting
+    /// every `interval` steps and keeping up to `limit` snapshots. At least
+    /// `interval * (limit - 1)` steps can always be undone.
+    pub fn with_limits(machine: &Machine, interval: usize, limit: usize) -> Self {
+        let mut recorder = Recorder {
+            interval: interval.max(1),
+            limit: limit.max(1),
+            snapshots: VecDeque::new(),
+            undo: Vec::new(),
+            pending: Undo::default(),
+            inputs: 0,
+        };
+        recorder.snapshot(machine);
+
+        Self {
+            recorder,
+            input: VecDeque::new(),
+            input_base: 0,
+        }
+    }
+
+    /// Forgets everything before the current state of `machine`, e.g. after
+    /// modifying it by hand.
+    pub fn reset(&mut self, machine: &Machine) {
+        self.recorder.snapshots.clear();
+        self.recorder.snapshot(machine);
+        self.trim_input();
+    }
+
+    /// Runs a single token, like [`Machine::step_with`], while recording it.
+    pub fn step(
+        &mut self,
+        machine: &mut Machine,
+        observer: &mut impl Observer,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+    ) -> State {
+        let before = self.recorder.snapshots.front().map(|s| s.steps);
+
+        let mut replay = Replay {
+            pos: self.recorder.inputs - self.input_base,
+            log: &mut self.input,
+            stdin,
+        };
+        let state = machine.step_with(&mut (&mut self.recorder, observer), &mut replay, stdout);
+
+        if self.recorder.snapshots.front().map(|s| s.steps) != before {
+            self.trim_input();
+        }
+
+        state
+    }
+
+    /// Undoes up to `n` steps. Returns how many were actually undone, which is
+    /// less than `n` if the history runs out.
+    pub fn step_back(&mut self, machine: &mut Machine, n: u64) -> u64 {
+        let start = machine.steps;
+        let oldest = self.recorder.snapshots.front().map_or(start, |s| s.steps);
+        let target = start.saturating_sub(n).max(oldest);
+
+        while machine.steps > target {
+            match self.recorder.undo.pop() {
+                Some(undo) => {
+                    for &(cell, old) in &undo.cells[..undo.len] {
+                        machine.tape[cell] = old;
+                    }
+
+                    machine.sp = undo.sp;
+                    machine.ip = undo.ip;
+                    machine.steps -= 1;
+
+                    if machine.code[undo.ip] == Token::In {
+                        self.recorder.inputs -= 1;
+                    }
+                }
+                None => self.rebuild(machine),
+            }
+        }
+
+        start - machine.steps
+    }
+
+    /// Restores the snapshot before the current one, then re-runs up to the
+    /// current state to rebuild the undo log in between.
+    fn rebuild(&mut self, machine: &mut Machine) {
+        let snapshots = &mut self.recorder.snapshots;
+        let Some(end) = snapshots.pop_back().map(|s| s.steps) else {
+            return;
+        };
+
+        let Some(snap) = snapshots.back() else {
+            // Can't happen while `step_back` stays above the oldest snapshot.
+            return;
+        };
+
+        machine.tape.clone_from(&snap.tape);
+        machine.sp = snap.sp;
+        machine.ip = snap.ip;
+        machine.steps = snap.steps;
+        self.recorder.inputs = snap.inputs;
+        self.recorder.undo.clear();
+
+        let mut eof = std::iter::empty();
+        let mut replay = Replay {
+            pos: self.recorder.inputs - self.input_base,
+            log: &mut self.input,
+            stdin: &mut eof,
+        };
+
+        // Any dumps on the way were already written the first time around.
+        let out = machine.dump.out.replace(Box::new(io::sink()));
+
+        while machine.steps < end {
+            machine.step_with(&mut self.recorder, &mut replay, &mut io::sink());
+        }
+
+        machine.dump.out = out;
+    }
+
+    /// Drops input that was read before the oldest snapshot.
+    fn trim_input(&mut self) {
+        let Some(oldest) = self.recorder.snapshots.front() else {
+            return;
+        };
+
+        let drop = oldest.inputs - self.input_base;
+        self.input.drain(..drop);
+        self.input_base = oldest.inputs;
+    }
+}
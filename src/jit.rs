@@ -0,0 +1,346 @@
+//! An x86-64 JIT backend for Linux.
+//!
+//! Translates optimized [`Token`]s directly into machine code, which is then
+//! run from an executable `mmap`'d buffer. `In`/`Out` call back into Rust.
+//!
+//! Only the default tape (30000 wrapping cells) and wrapping byte-sized cells
+//! are supported; [`compile`] returns `None` for anything else (including
+//! other platforms), in which case the caller should fall back to
+//! [`interpret`](crate::interpret).
+
+use std::io::Write;
+
+use crate::token::Token;
+use crate::Cell;
+
+/// A program compiled to native code.
+pub struct JitProgram {
+    #[cfg(all(
+        target_os = "linux",
+        target_arch = "x86_64",
+        not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+    ))]
+    buf: native::ExecBuffer,
+
+    #[cfg(not(all(
+        target_os = "linux",
+        target_arch = "x86_64",
+        not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+    )))]
+    never: std::convert::Infallible,
+}
+
+/// Compiles `code` to native code, if possible.
+///
+/// Returns `None` if the platform or the current cell/tape configuration is
+/// unsupported, or if the program contains a [`Dump`](Token::Dump).
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+pub fn compile(code: &[Token]) -> Option<JitProgram> {
+    #[cfg(all(
+        target_os = "linux",
+        target_arch = "x86_64",
+        not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+    ))]
+    {
+        let bytes = native::assemble(code)?;
+        let buf = native::ExecBuffer::new(&bytes)?;
+        Some(JitProgram { buf })
+    }
+
+    #[cfg(not(all(
+        target_os = "linux",
+        target_arch = "x86_64",
+        not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+    )))]
+    {
+        let _ = code;
+        None
+    }
+}
+
+impl JitProgram {
+    /// Runs the compiled program. Behaves identically to
+    /// [`interpret`](crate::interpret).
+    pub fn run(&self, stdin: &mut impl Iterator<Item = u8>, stdout: &mut impl Write) {
+        self.run_on(&mut [0; 30000], stdin, stdout);
+    }
+
+    /// Runs the compiled program on `tape`, which must have the default 30000
+    /// cells.
+    pub(crate) fn run_on(
+        &self,
+        tape: &mut [Cell; 30000],
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+    ) {
+        #[cfg(all(
+            target_os = "linux",
+            target_arch = "x86_64",
+            not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+        ))]
+        {
+            let mut ctx = native::Context { stdin, stdout };
+
+            // SAFETY: the buffer was assembled by `native::assemble`, which
+            // only emits in-bounds tape accesses and follows the SysV ABI.
+            // Cells are bytes, whether signed or not.
+            unsafe { self.buf.call(tape.as_mut_ptr().cast(), &mut ctx) };
+
+            ctx.stdout
+                .flush()
+                .unwrap_or_else(|e| crate::fail(crate::Failure::Io, "failed to flush stdout", e));
+        }
+
+        #[cfg(not(all(
+            target_os = "linux",
+            target_arch = "x86_64",
+            not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+        )))]
+        {
+            let _ = (tape, stdin, stdout);
+            match self.never {}
+        }
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    target_arch = "x86_64",
+    not(any(feature = "dynamic_array", feature = "nowrap", feature = "wide_cell")),
+))]
+mod native {
+    use std::ffi::c_void;
+    use std::io::Write;
+
+    use crate::emit::x86::{self, Hook};
+    use crate::helper::{fail, Failure};
+    use crate::token::Token;
+
+    const PROT_READ: i32 = 1;
+    const PROT_WRITE: i32 = 2;
+    const PROT_EXEC: i32 = 4;
+    const MAP_PRIVATE: i32 = 2;
+    const MAP_ANONYMOUS: i32 = 0x20;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            off: i64,
+        ) -> *mut c_void;
+        fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+        fn munmap(addr: *mut c_void, len: usize) -> i32;
+    }
+
+    /// The I/O handles the generated code calls back into.
+    pub struct Context<'a> {
+        pub stdin: &'a mut dyn Iterator<Item = u8>,
+        pub stdout: &'a mut dyn Write,
+    }
+
+    extern "sysv64" fn jit_in(ctx: *mut Context) -> u8 {
+        // SAFETY: the generated code only ever passes along the pointer
+        // given to `ExecBuffer::call`.
+        let ctx = unsafe { &mut *ctx };
+        ctx.stdin.next().unwrap_or(0)
+    }
+
+    extern "sysv64" fn jit_out(ctx: *mut Context, byte: u8) {
+        // SAFETY: see `jit_in`.
+        let ctx = unsafe { &mut *ctx };
+        ctx.stdout
+            .write_all(&[byte])
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to write to output", e));
+
+        if byte == b'\n' {
+            ctx.stdout
+                .flush()
+                .unwrap_or_else(|e| fail(Failure::Io, "failed to flush stdout", e));
+        }
+    }
+
+    extern "sysv64" fn jit_end(ctx: *mut Context) {
+        // SAFETY: see `jit_in`.
+        let ctx = unsafe { &mut *ctx };
+        ctx.stdout
+            .flush()
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to flush stdout", e));
+
+        loop {
+            std::thread::sleep(std::time::Duration::new(1000000, 0));
+        }
+    }
+
+    /// A read-only, executable memory mapping.
+    pub struct ExecBuffer {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    impl ExecBuffer {
+        pub fn new(code: &[u8]) -> Option<Self> {
+            let len = code.len().max(1);
+
+            // SAFETY: a fresh anonymous mapping; checked for failure below.
+            let ptr = unsafe {
+                mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    PROT_READ | PROT_WRITE,
+                    MAP_PRIVATE | MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+
+            if ptr as isize == -1 {
+                return None;
+            }
+
+            // SAFETY: the mapping is at least `code.len()` bytes and writable.
+            unsafe {
+                std::ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, code.len());
+
+                if mprotect(ptr, len, PROT_READ | PROT_EXEC) != 0 {
+                    munmap(ptr, len);
+                    return None;
+                }
+            }
+
+            Some(Self { ptr, len })
+        }
+
+        /// # Safety
+        ///
+        /// `tape` must point to at least 30000 writable bytes.
+        pub unsafe fn call(&self, tape: *mut u8, ctx: &mut Context) {
+            let f: extern "sysv64" fn(*mut u8, *mut Context) = std::mem::transmute(self.ptr);
+            f(tape, ctx);
+        }
+    }
+
+    impl Drop for ExecBuffer {
+        fn drop(&mut self) {
+            // SAFETY: `ptr` and `len` describe a mapping we own.
+            unsafe { munmap(self.ptr, self.len) };
+        }
+    }
+
+    /// Emits machine code for `code`. On top of the registers used by
+    /// [`x86`], `r13` holds the `Context` pointer.
+    pub fn assemble(code: &[Token]) -> Option<Vec<u8>> {
+        let mut asm = Vec::new();
+
+        // push rbx; push r12; push r13
+        asm.extend([0x53, 0x41, 0x54, 0x41, 0x55]);
+        // mov rbx, rdi
+        asm.extend([0x48, 0x89, 0xfb]);
+        // xor r12d, r12d
+        asm.extend([0x45, 0x31, 0xe4]);
+        // mov r13, rsi
+        asm.extend([0x49, 0x89, 0xf5]);
+
+        x86::assemble(code, &mut asm, |asm, hook| {
+            match hook {
+                Hook::In => {
+                    call(asm, jit_in as *const ());
+                    // mov byte [rbx+r12], al
+                    asm.extend([0x42, 0x88, 0x04, 0x23]);
+                }
+                Hook::Out => {
+                    // movzx esi, byte [rbx+r12]
+                    asm.extend([0x42, 0x0f, 0xb6, 0x34, 0x23]);
+                    call(asm, jit_out as *const ());
+                }
+                Hook::End => call(asm, jit_end as *const ()),
+                Hook::Dump => return None,
+            }
+
+            Some(())
+        })?;
+
+        // pop r13; pop r12; pop rbx; ret
+        asm.extend([0x41, 0x5d, 0x41, 0x5c, 0x5b, 0xc3]);
+
+        Some(asm)
+    }
+
+    /// Calls `func` with the context as its first argument.
+    fn call(asm: &mut Vec<u8>, func: *const ()) {
+        // mov rdi, r13
+        asm.extend([0x4c, 0x89, 0xef]);
+        // mov rax, func
+        asm.extend([0x48, 0xb8]);
+        asm.extend((func as u64).to_le_bytes());
+        // call rax
+        asm.extend([0xff, 0xd0]);
+    }
+}
+
+#[cfg(all(test, feature = "jit"))]
+mod tests {
+    use super::*;
+    use crate::machine::Machine;
+    use crate::program::Program;
+
+    /// Runs `source` both compiled and interpreted, checking that they give
+    /// the same output and leave the same tape behind.
+    fn check(source: &str, input: &[u8]) {
+        let program = Program::new(source.to_string());
+        let Some(jit) = compile(&program.code) else {
+            // Unsupported in this build.
+            return;
+        };
+
+        let mut machine = Machine::new(&program.code);
+        let mut expected = Vec::new();
+        machine.run(&mut input.iter().copied(), &mut expected);
+
+        let mut tape = [0; 30000];
+        let mut output = Vec::new();
+        jit.run_on(&mut tape, &mut input.iter().copied(), &mut output);
+
+        assert_eq!(output, expected, "output of `{source}`");
+        assert_eq!(tape[..], machine.tape[..], "tape of `{source}`");
+    }
+
+    #[test]
+    fn wraparound() {
+        check("-.>+[+]+.", b"");
+        check("<+++.>>-", b"");
+    }
+
+    #[test]
+    fn scan() {
+        check("+>+>+>>+<<<<[>]+", b"");
+        check("+>>>>>+>+>+[<]+", b"");
+    }
+
+    #[test]
+    fn add_sub_dup() {
+        check("+++[->>+<<]>>.", b"");
+        check("+++[->>+++<<]>>.", b"");
+        check("+++[->>-<<]>>.", b"");
+        check("+++[->+>++<<]>.>.", b"");
+        check(">>>+++[-<<+<+++>>>]<<.<.", b"");
+    }
+
+    #[test]
+    fn input_at_eof() {
+        check(",[.,]", b"hello");
+        check("+>+>+<<,>,>,", b"a");
+    }
+
+    #[test]
+    fn hello_world() {
+        check(
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.",
+            b"",
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Limits on how long a program may run.
+
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use crate::machine::{Machine, Observer};
+
+/// Which limit a program ran into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exceeded {
+    /// It ran too many steps.
+    Steps(u64),
+    /// It ran for too long.
+    Time(Duration),
+}
+
+impl Display for Exceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exceeded::Steps(steps) => write!(f, "exceeded {steps} steps"),
+            Exceeded::Time(time) => write!(f, "timed out after {time:?}"),
+        }
+    }
+}
+
+/// An [`Observer`] that stops a machine once it runs too many steps, or for
+/// too long.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// The most steps the machine may run.
+    pub steps: Option<u64>,
+    /// The longest the machine may run, counted from when the limits were
+    /// created.
+    pub time: Option<Duration>,
+    /// Which limit stopped the machine, if any.
+    pub exceeded: Option<Exceeded>,
+
+    start: Instant,
+}
+
+impl Limits {
+    /// Creates limits, starting the clock now.
+    pub fn new(steps: Option<u64>, time: Option<Duration>) -> Self {
+        Self {
+            steps,
+            time,
+            exceeded: None,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Observer for Limits {
+    fn before(&mut self, machine: &Machine) -> bool {
+        if let Some(steps) = self.steps {
+            if machine.steps >= steps {
+                self.exceeded = Some(Exceeded::Steps(steps));
+                return false;
+            }
+        }
+
+        // Checking the clock is slow, so only do it every so often.
+        if let Some(time) = self.time {
+            if machine.steps.is_multiple_of(4096) && self.start.elapsed() >= time {
+                self.exceeded = Some(Exceeded::Time(time));
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,383 @@
+//! A steppable brain* machine, for when running to completion isn't enough.
+
+use std::io::Write;
+
+use crate::dump::{DumpConfig, DumpState};
+use crate::helper::{fail, wrap_cell, wrap_goto, Failure};
+use crate::tape;
+use crate::token::Token;
+use crate::{Cell, CellMod};
+
+/// What a [`Machine`] is doing after a step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// There are more tokens to run.
+    Running,
+    /// The program ran off the end.
+    Finished,
+    /// The program reached an [`End`](Token::End) on a nonzero cell, and will
+    /// never progress.
+    Hanging,
+    /// An [`Observer`] stopped the machine. Running or stepping again resumes
+    /// it.
+    Stopped,
+}
+
+/// Hooks into each step of a [`Machine`], e.g. for watchpoints or profiling.
+pub trait Observer {
+    /// Called before the token at `machine.ip` runs. Returning false stops the
+    /// machine without running it.
+    fn before(&mut self, machine: &Machine) -> bool {
+        let _ = machine;
+        true
+    }
+
+    /// Called after the token at `ip` ran. Returning false stops the machine.
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        let _ = (machine, ip);
+        true
+    }
+}
+
+impl Observer for () {}
+
+impl<O: Observer + ?Sized> Observer for &mut O {
+    fn before(&mut self, machine: &Machine) -> bool {
+        (**self).before(machine)
+    }
+
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        (**self).after(machine, ip)
+    }
+}
+
+impl<O: Observer> Observer for Option<O> {
+    fn before(&mut self, machine: &Machine) -> bool {
+        self.as_mut().is_none_or(|o| o.before(machine))
+    }
+
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        self.as_mut().is_none_or(|o| o.after(machine, ip))
+    }
+}
+
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn before(&mut self, machine: &Machine) -> bool {
+        self.0.before(machine) & self.1.before(machine)
+    }
+
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        self.0.after(machine, ip) & self.1.after(machine, ip)
+    }
+}
+
+/// The state of a running program: its tape, tape pointer and instruction
+/// pointer.
+///
+/// Note that each bracket must have already been matched to its pair, i.e.
+/// through `optimize`.
+#[derive(Debug)]
+pub struct Machine<'a> {
+    /// The program being run.
+    pub code: &'a [Token],
+    /// The tape. With feature `dynamic_array`, this grows as needed.
+    pub tape: Vec<Cell>,
+    /// The tape pointer.
+    pub sp: usize,
+    /// The instruction pointer, i.e. the index of the next token to run.
+    pub ip: usize,
+    /// How many tokens have been run.
+    pub steps: u64,
+    /// How to print a [`Dump`](Token::Dump).
+    pub dump: DumpConfig<'a>,
+
+    highest: usize,
+}
+
+impl<'a> Machine<'a> {
+    /// Creates a machine at the start of `code`, with an empty tape.
+    pub fn new(code: &'a [Token]) -> Self {
+        Self {
+            code,
+
+            #[cfg(not(feature = "dynamic_array"))]
+            tape: vec![0; 30000],
+            #[cfg(feature = "dynamic_array")]
+            tape: vec![0; 1024],
+
+            sp: 0,
+            ip: 0,
+            steps: 0,
+            dump: DumpConfig::default(),
+
+            highest: 0,
+        }
+    }
+
+    /// Creates a machine at the start of `code`, with the tape starting with
+    /// `cells` and the tape pointer at `sp`. With feature `dynamic_array`, the
+    /// tape grows to fit; otherwise, they must fit on the fixed-size tape.
+    pub fn with_tape(code: &'a [Token], cells: &[Cell], sp: usize) -> Result<Self, tape::Error> {
+        let mut machine = Self::new(code);
+        let len = cells.len().max(sp + 1);
+
+        if len > machine.tape.len() {
+            #[cfg(not(feature = "dynamic_array"))]
+            return Err(tape::Error::TooLong {
+                len,
+                max: machine.tape.len(),
+            });
+
+            #[cfg(feature = "dynamic_array")]
+            machine.tape.resize(len, 0);
+        }
+
+        machine.tape[..cells.len()].copy_from_slice(cells);
+        machine.sp = sp;
+        machine.highest = sp;
+
+        Ok(machine)
+    }
+
+    /// Returns true if there are no more tokens to run.
+    pub fn finished(&self) -> bool {
+        self.ip >= self.code.len()
+    }
+
+    /// Gets the cell at `i`, which is zero if it's off the end of the tape.
+    pub fn cell(&self, i: usize) -> Cell {
+        self.tape.get(i).copied().unwrap_or(0)
+    }
+
+    /// Gets the cells the current token may write to. Cells only read by a
+    /// token, such as by [`Out`](Token::Out), aren't included.
+    pub fn writes(&self) -> impl Iterator<Item = usize> {
+        let sp = self.sp;
+        let cells = match self.code.get(self.ip) {
+            Some(Token::Inc(_) | Token::Dec(_) | Token::In | Token::Zero | Token::Set(_)) => {
+                [Some(sp), None, None]
+            }
+            Some(&Token::Add(i, _) | &Token::Sub(i)) => [Some(wrap_goto(sp, i)), Some(sp), None],
+            Some(&Token::Dup(i1, _, i2, _)) => [
+                Some(wrap_goto(sp, i1)),
+                Some(wrap_goto(sp, i1 + i2)),
+                Some(sp),
+            ],
+            _ => [None; 3],
+        };
+
+        cells.into_iter().flatten()
+    }
+
+    /// Runs until the program finishes or hangs.
+    pub fn run(&mut self, stdin: &mut impl Iterator<Item = u8>, stdout: &mut impl Write) -> State {
+        self.run_with(&mut (), stdin, stdout)
+    }
+
+    /// Runs until the program finishes, hangs, or `observer` stops it.
+    pub fn run_with(
+        &mut self,
+        observer: &mut impl Observer,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+    ) -> State {
+        while let Some(&tok) = self.code.get(self.ip) {
+            let ip = self.ip;
+
+            if !observer.before(self) {
+                return State::Stopped;
+            }
+
+            if !self.exec(tok, stdin, stdout) {
+                return State::Hanging;
+            }
+
+            if !observer.after(self, ip) {
+                return if self.finished() {
+                    State::Finished
+                } else {
+                    State::Stopped
+                };
+            }
+        }
+
+        State::Finished
+    }
+
+    /// Runs a single token.
+    pub fn step(&mut self, stdin: &mut impl Iterator<Item = u8>, stdout: &mut impl Write) -> State {
+        self.step_with(&mut (), stdin, stdout)
+    }
+
+    /// Runs a single token, unless `observer` stops it.
+    pub fn step_with(
+        &mut self,
+        observer: &mut impl Observer,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+    ) -> State {
+        let Some(&tok) = self.code.get(self.ip) else {
+            return State::Finished;
+        };
+        let ip = self.ip;
+
+        if !observer.before(self) {
+            return State::Stopped;
+        }
+
+        if !self.exec(tok, stdin, stdout) {
+            State::Hanging
+        } else if self.finished() {
+            observer.after(self, ip);
+            State::Finished
+        } else if !observer.after(self, ip) {
+            State::Stopped
+        } else {
+            State::Running
+        }
+    }
+
+    /// Runs `tok`, which must be the current token. Returns false if the
+    /// program hangs.
+    #[inline(always)]
+    fn exec(
+        &mut self,
+        tok: Token,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+    ) -> bool {
+        let sp = self.sp;
+
+        match tok {
+            Token::Inc(i) => self.tape[sp] = wrap_cell(self.tape[sp], i as CellMod),
+            Token::Dec(i) => self.tape[sp] = wrap_cell(self.tape[sp], -(i as CellMod)),
+            Token::Goto(i) => self.goto(wrap_goto(sp, i)),
+            Token::In => self.tape[sp] = stdin.next().unwrap_or(0) as Cell,
+            Token::Out => {
+                #[allow(clippy::unnecessary_cast)]
+                let bytes = self.tape[sp].to_ne_bytes();
+                stdout
+                    .write_all(&bytes)
+                    .unwrap_or_else(|e| fail(Failure::Io, "failed to write to output", e));
+
+                #[allow(clippy::unnecessary_cast)]
+                if self.tape[sp] as u8 == b'\n' {
+                    stdout
+                        .flush()
+                        .unwrap_or_else(|e| fail(Failure::Io, "failed to flush stdout", e));
+                }
+            }
+
+            Token::LBrack(i) => {
+                if self.tape[sp] == 0 {
+                    self.ip = i;
+                }
+            }
+
+            Token::RBrack(i) => {
+                if self.tape[sp] != 0 {
+                    self.ip = i;
+                }
+            }
+
+            Token::Zero => self.tape[sp] = 0,
+            Token::Set(i) => self.tape[sp] = i,
+            Token::Add(i, mul) => {
+                let v = self.tape[sp];
+                let cell = self.target(wrap_goto(sp, i));
+                *cell = wrap_cell(*cell, (v * mul) as CellMod);
+                self.tape[sp] = 0;
+            }
+            Token::Sub(i) => {
+                let v = self.tape[sp];
+                let cell = self.target(wrap_goto(sp, i));
+                *cell = wrap_cell(*cell, -(v as CellMod));
+                self.tape[sp] = 0;
+            }
+            Token::Dup(i1, mul1, i2, mul2) => {
+                let v = self.tape[sp];
+
+                let cell = self.target(wrap_goto(sp, i1));
+                *cell = wrap_cell(*cell, (v * mul1) as CellMod);
+
+                let cell = self.target(wrap_goto(sp, i1 + i2));
+                *cell = cell.wrapping_add(v * mul2);
+
+                self.tape[sp] = 0;
+            }
+            Token::Scan(i) => {
+                let mut sp = sp;
+                while self.tape[sp] != 0 {
+                    sp = wrap_goto(sp, i);
+                    self.goto(sp);
+                }
+            }
+
+            Token::End => {
+                if self.tape[sp] != 0 {
+                    return false;
+                }
+            }
+
+            Token::Dump => return self.dump(),
+        }
+
+        self.ip += 1;
+        self.steps += 1;
+
+        true
+    }
+
+    /// Moves the tape pointer, growing the tape if needed.
+    #[inline]
+    fn goto(&mut self, sp: usize) {
+        self.sp = sp;
+
+        if sp > self.highest {
+            self.highest = sp;
+        }
+
+        #[cfg(feature = "dynamic_array")]
+        if sp >= self.tape.len() {
+            self.tape.resize(sp + 1, 0);
+        }
+    }
+
+    /// Gets a cell to modify, growing the tape if needed.
+    #[inline]
+    fn target(&mut self, i: usize) -> &mut Cell {
+        #[cfg(feature = "dynamic_array")]
+        if i >= self.tape.len() {
+            self.tape.resize(i + 1, 0);
+        }
+
+        &mut self.tape[i]
+    }
+
+    /// Prints the tape, the tape pointer, and the code around the current
+    /// token, as configured by [`dump`](Self::dump), then moves on to the next
+    /// token. Kept out of line, and out of the tail of [`exec`](Self::exec),
+    /// so that it doesn't slow down every other token.
+    #[cold]
+    #[inline(never)]
+    fn dump(&mut self) -> bool {
+        self.highest = self.highest.max(self.sp);
+
+        let state = DumpState {
+            code: self.code,
+            tape: &self.tape,
+            ip: self.ip,
+            sp: self.sp,
+            steps: self.steps,
+            highest: self.highest,
+        };
+
+        self.dump
+            .write(&state)
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to write dump", e));
+
+        self.ip += 1;
+        self.steps += 1;
+        true
+    }
+}
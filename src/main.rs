@@ -1,80 +1,943 @@
 use std::{
     fs::{self, File},
-    io::{stdout, stdin, BufReader, Read},
+    io::{self, stderr, stdin, stdout, BufReader, BufWriter, Read, Write},
+    path::Path,
+    process::exit,
+    time::Duration,
 };
 
 use sarge::prelude::*;
 
 use brim::{
-    helper::{err, warn},
-    interpret, parse,
-    token::optimize,
+    bench, bytecode, coverage,
+    debugger::Debugger,
+    golden::{self, Case},
+    helper::{err, fail, line_col, parse_range, warn, Failure},
+    limit::{Exceeded, Limits},
+    machine::{Machine, Observer, State},
+    profile::Profile,
+    program::Program,
+    repl::Repl,
+    tape,
+    token::{self, ParseOptions, Token},
+    trace::{Filter, Trace},
+    tui::Tui,
+    watch::Watch,
 };
 
-#[cfg(feature = "debug")]
 sarge! {
     Args,
 
     'h' help: bool,
     #ok 'i' input: String,
     #ok 'o' output: String,
+    #ok 'e' eval: String,
+    #ok emit: String,
+    #ok width: usize,
+    #ok watch: Vec<String>,
+    profile: bool,
+    #ok coverage: String,
+    #ok trace: String,
+    #ok trace_filter: Vec<String>,
+    #ok dump_tape: String,
+    #ok tape_init: String,
+    #ok tape_pointer: usize,
+    #ok max_steps: u64,
+    #ok timeout: f64,
+    #ok runs: usize,
+    #ok warmup: usize,
+    compare: bool,
+    exit_code: bool,
+    #ok exit_cell: usize,
 
+    #ok debug_chars: String,
     #ok 'w' @BRIM_DEBUG_WIDTH debug_width: usize,
-}
-
-#[cfg(not(feature = "debug"))]
-sarge! {
-    Args,
-
-    'h' help: bool,
-    #ok 'i' input: String,
-    #ok 'o' output: String,
+    #ok dump_window: usize,
+    #ok dump_format: String,
+    #ok dump_file: String,
+    dump_json: bool,
+    link: bool,
+    bang_input: bool,
 }
 
 fn main() {
     let (args, files) = Args::parse().unwrap_or_else(|e| err("failed to parse arguments", e));
 
-    if args.help || files.is_empty() {
+    // The first positional argument is the path brim was run as. If the next
+    // is a subcommand, the rest are its arguments; otherwise, they're all
+    // programs to run in order (after any `-e` program).
+    let files = files.get(1..).unwrap_or_default();
+
+    if args.help || (files.is_empty() && args.eval.is_none()) {
         warn(include_str!("usage.txt"));
 
         return;
     }
 
-    let mut stdin = if let Some(i) = args.input {
-        let file = File::open(i).unwrap_or_else(|e| err("failed to open input file", e));
+    match files.first().map(String::as_str) {
+        Some("build") => {
+            build(&args, &files[1..]);
+
+            return;
+        }
+        Some("compile") => {
+            compile(&args, &files[1..]);
+
+            return;
+        }
+        Some("debug") => {
+            debug(&args, &files[1..]);
+
+            return;
+        }
+        Some("tui") => {
+            tui(&args, &files[1..]);
+
+            return;
+        }
+        Some("test") => {
+            test(&args, &files[1..]);
+
+            return;
+        }
+        Some("bench") => {
+            bench(&args, &files[1..]);
+
+            return;
+        }
+        Some("repl") => {
+            repl(&args, &files[1..]);
+
+            return;
+        }
+        _ => {}
+    }
+
+    let mut stdin = input(&args);
+    let mut stdout = output(&args);
+    let mut reports = Reports::open(&args);
+
+    let eval = args
+        .eval
+        .iter()
+        .map(|code| ("-e", parse(&args, "-e", code.clone())));
+    let programs: Vec<_> = eval
+        .chain(files.iter().map(|f| (f.as_str(), load(&args, f))))
+        .collect();
+
+    // Separate machines would all save their tapes to the same file.
+    if args.dump_tape.is_some() && programs.len() > 1 && !args.link {
+        err(
+            "invalid arguments",
+            "`--dump-tape` takes a single program, unless `--link` is given",
+        );
+    }
+
+    // With `--link`, every program runs on the same machine, one after another.
+    let mut linked = None;
+    let mut status = 0;
 
-        Box::new(BufReader::new(file)) as Box<dyn Read>
+    for (i, (filename, program)) in programs.iter().enumerate() {
+        reports.last = i + 1 == programs.len();
+
+        if let Some(format) = &args.emit {
+            stdout
+                .write_all(emit(&args, format, &program.code).as_bytes())
+                .unwrap_or_else(|e| fail(Failure::Io, "failed to write to output", e));
+
+            continue;
+        }
+
+        let mut fresh;
+        let machine = if args.link {
+            let machine = linked.get_or_insert_with(|| new_machine(&args, program));
+            machine.code = &program.code;
+            machine.ip = 0;
+            machine
+        } else {
+            fresh = new_machine(&args, program);
+            &mut fresh
+        };
+
+        // Input embedded in the source comes first.
+        let mut stdin = program.input.iter().copied().chain(&mut stdin);
+
+        run(
+            &args,
+            filename,
+            program,
+            machine,
+            &mut stdin,
+            &mut stdout,
+            &mut reports,
+        );
+
+        if args.exit_code || args.exit_cell.is_some() {
+            let cell = machine.cell(args.exit_cell.unwrap_or(machine.sp));
+            status = i32::from(cell.to_le_bytes()[0]);
+        }
+    }
+
+    if let Some(trace) = &mut reports.trace {
+        trace
+            .flush()
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to write trace", e));
+    }
+
+    exit(status);
+}
+
+/// The files that instrumentation writes to, shared by every program run.
+struct Reports {
+    dump: Option<File>,
+    coverage: Option<File>,
+    trace: Option<BufWriter<File>>,
+    trace_filters: Vec<Filter>,
+    tape: Option<File>,
+    /// Whether the program being run is the last one.
+    last: bool,
+}
+
+impl Reports {
+    fn open(args: &Args) -> Self {
+        let create = |path: &String, what: &str| {
+            File::create(path)
+                .unwrap_or_else(|e| fail(Failure::Io, format!("failed to open {what} file"), e))
+        };
+
+        let trace_filters = args
+            .trace_filter
+            .iter()
+            .flatten()
+            .map(|filter| {
+                filter
+                    .parse()
+                    .unwrap_or_else(|e| err("invalid trace filter", e))
+            })
+            .collect();
+
+        Self {
+            dump: dump_file(args),
+            coverage: args.coverage.as_ref().map(|path| create(path, "coverage")),
+            trace: args
+                .trace
+                .as_ref()
+                .map(|path| BufWriter::new(create(path, "trace"))),
+            trace_filters,
+            tape: args.dump_tape.as_ref().map(|path| create(path, "tape")),
+            last: true,
+        }
+    }
+}
+
+/// Opens the program's input: the `--input` file, or stdin.
+fn input(args: &Args) -> Box<dyn Iterator<Item = u8>> {
+    if let Some(i) = &args.input {
+        let file =
+            File::open(i).unwrap_or_else(|e| fail(Failure::Io, "failed to open input file", e));
+
+        Box::new(
+            BufReader::new(file)
+                .bytes()
+                .map(|data| data.unwrap_or_else(|e| fail(Failure::Io, "failed to read input", e))),
+        )
     } else {
-        Box::new(BufReader::new(stdin())) as Box<dyn Read>
-    }.bytes().map(|data| data.unwrap_or_else(|e| err("failed to read input", e)));
+        // Stdin is already buffered internally. Reading it a byte at a time,
+        // without holding onto the lock, lets it be shared with e.g. the
+        // debugger's prompt.
+        Box::new(std::iter::from_fn(|| {
+            let mut byte = [0];
+            match stdin().read(&mut byte) {
+                Ok(0) => None,
+                Ok(_) => Some(byte[0]),
+                Err(e) => fail(Failure::Io, "failed to read input", e),
+            }
+        }))
+    }
+}
+
+/// Opens the program's output: the `--output` file, or stdout.
+fn output(args: &Args) -> Box<dyn Write> {
+    if let Some(o) = &args.output {
+        let file =
+            File::create(o).unwrap_or_else(|e| fail(Failure::Io, "failed to open output file", e));
+
+        Box::new(file)
+    } else {
+        Box::new(stdout())
+    }
+}
+
+/// Reads a program's file, or stdin if `filename` is `-`.
+fn read(filename: &str) -> Vec<u8> {
+    if filename == "-" {
+        let mut input = Vec::new();
+        stdin()
+            .read_to_end(&mut input)
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to read program from stdin", e));
+
+        return input;
+    }
+
+    fs::read(filename).unwrap_or_else(|e| fail(Failure::Io, "failed to read file", e))
+}
+
+/// Reads a program, either from source or from bytecode.
+fn load(args: &Args, filename: &str) -> Program {
+    decode(args, filename, read(filename))
+        .unwrap_or_else(|e| fail(Failure::Parse, "invalid input", e))
+}
+
+/// Decodes a program from the contents of its file, either source or
+/// bytecode. Returns why it's invalid, if it is.
+fn decode(args: &Args, filename: &str, input: Vec<u8>) -> Result<Program, String> {
+    if bytecode::is_bytecode(&input) {
+        return bytecode::deserialize(&input)
+            .map(Program::from_tokens)
+            .map_err(|e| format!("invalid bytecode: {e}"));
+    }
+
+    try_parse(args, filename, String::from_utf8_lossy(&input).into_owned())
+}
+
+/// Parses and optimizes a program's source code, exiting if it's invalid.
+fn parse(args: &Args, filename: &str, source: String) -> Program {
+    try_parse(args, filename, source).unwrap_or_else(|e| fail(Failure::Parse, "invalid input", e))
+}
+
+/// Parses and optimizes a program's source code. Returns any unmatched
+/// bracket by its position in `filename`.
+fn try_parse(args: &Args, filename: &str, source: String) -> Result<Program, String> {
+    let options = parse_options(args);
+
+    if let Some(offset) = token::unmatched_bracket(options.split_input(&source).0) {
+        let (line, col) = line_col(&source, offset);
+        let which = if source[offset..].starts_with('[') {
+            "opening"
+        } else {
+            "closing"
+        };
+
+        return Err(format!(
+            "unmatched {which} bracket at {filename}:{line}:{col}"
+        ));
+    }
+
+    Ok(Program::with_options(source, options))
+}
+
+/// Gets the parse options from the arguments.
+fn parse_options(args: &Args) -> ParseOptions {
+    let mut options = ParseOptions::default();
+
+    if let Some(chars) = &args.debug_chars {
+        options.debug_chars = chars.chars().collect();
+    }
+
+    if args.bang_input {
+        options.input_separator = Some('!');
+    }
+
+    options
+}
+
+/// Derives an output filename from an input filename.
+fn output_name(filename: &str, ext: &str) -> String {
+    if filename == "-" {
+        err("invalid arguments", "no output file given");
+    }
+
+    let stem = Path::new(filename)
+        .file_stem()
+        .unwrap_or_else(|| err("invalid arguments", "no output file given"));
+
+    Path::new(stem)
+        .with_extension(ext)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Runs a program in the interactive debugger.
+fn debug(args: &Args, files: &[String]) {
+    let [filename] = files else {
+        err("invalid arguments", "`debug` takes exactly one file");
+    };
+
+    let program = load(args, filename);
+
+    let mut debugger = Debugger::with_machine(&program, new_machine(args, &program));
+    configure_dump(
+        args,
+        &program,
+        &mut debugger.machine,
+        dump_file(args).as_ref(),
+    );
+
+    // Like the program's input, this locks stdin only while reading a line.
+    let mut commands = std::iter::from_fn(|| {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(e) => Some(Err(e)),
+        }
+    });
+
+    debugger
+        .run(
+            &mut commands,
+            &mut program.input.iter().copied().chain(input(args)),
+            &mut output(args),
+            &mut stderr(),
+        )
+        .unwrap_or_else(|e| fail(Failure::Io, "debugger I/O failed", e));
+}
+
+/// Creates a machine for a program, with the tape set up by `--tape-init` and
+/// `--tape-pointer`: a JSON or CSV list of cells if the file ends in `.json` or
+/// `.csv`, otherwise raw bytes (or a tape saved by `--dump-tape`).
+fn new_machine<'a>(args: &Args, program: &'a Program) -> Machine<'a> {
+    let cells = args
+        .tape_init
+        .as_ref()
+        .map(|path| {
+            let bytes =
+                fs::read(path).unwrap_or_else(|e| fail(Failure::Io, "failed to read tape file", e));
+
+            match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+                Some("json" | "csv") => tape::from_list(&String::from_utf8_lossy(&bytes)),
+                _ => tape::from_bytes(&bytes),
+            }
+            .unwrap_or_else(|e| fail(Failure::Parse, "invalid tape file", e))
+        })
+        .unwrap_or_default();
+
+    Machine::with_tape(&program.code, &cells, args.tape_pointer.unwrap_or(0))
+        .unwrap_or_else(|e| fail(Failure::TapeOverflow, "invalid initial tape", e))
+}
+
+/// Runs the interactive REPL.
+fn repl(args: &Args, files: &[String]) {
+    if !files.is_empty() {
+        err("invalid arguments", "`repl` takes no files");
+    }
+
+    // Like in the debugger, this locks stdin only while reading a line.
+    let mut lines = std::iter::from_fn(|| {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(e) => Some(Err(e)),
+        }
+    });
+
+    Repl::new(parse_options(args))
+        .run(
+            &mut lines,
+            &mut input(args),
+            &mut output(args),
+            &mut stderr(),
+        )
+        .unwrap_or_else(|e| fail(Failure::Io, "REPL I/O failed", e));
+}
+
+/// Opens the `--dump-file`, if given.
+fn dump_file(args: &Args) -> Option<File> {
+    args.dump_file.as_ref().map(|path| {
+        File::create(path).unwrap_or_else(|e| fail(Failure::Io, "failed to open dump file", e))
+    })
+}
+
+/// Sets up how a machine prints [`Dump`](brim::token::Token::Dump)s, from
+/// `--debug-width` and the `--dump-*` options.
+fn configure_dump<'a>(
+    args: &Args,
+    program: &'a Program,
+    machine: &mut Machine<'a>,
+    file: Option<&File>,
+) {
+    let dump = &mut machine.dump;
+    dump.program = Some(program);
+
+    if let Some(file) = file {
+        let file = file
+            .try_clone()
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to open dump file", e));
+        dump.out = Some(Box::new(file));
+    }
+
+    dump.width = args.debug_width.unwrap_or(8);
+    dump.window = args.dump_window;
+    dump.json = args.dump_json;
+
+    if let Some(format) = &args.dump_format {
+        dump.format = format
+            .parse()
+            .unwrap_or_else(|e| err("invalid dump format", e));
+    }
+}
 
-    if let Some(o) = args.output {
-        let mut file = File::create(o).unwrap_or_else(|e| err("failed to open output file", e));
+/// Runs a program in the full-screen terminal UI. Once the user quits, its
+/// output is written as usual.
+fn tui(args: &Args, files: &[String]) {
+    let [filename] = files else {
+        err("invalid arguments", "`tui` takes exactly one file");
+    };
 
-        for filename in files.iter().skip(1) {
-            let input = fs::read_to_string(filename).unwrap_or_else(|e| err("failed", e));
+    let program = load(args, filename);
 
-            let toks = parse(&input);
-            let toks = optimize(&toks);
+    let input = args
+        .input
+        .as_ref()
+        .map(|i| fs::read(i).unwrap_or_else(|e| fail(Failure::Io, "failed to read input file", e)));
 
-            #[cfg(not(feature = "debug"))]
-            interpret(&toks, &mut stdin, &mut file);
+    let mut tui = Tui::new(&program, filename, input);
+    tui.run()
+        .unwrap_or_else(|e| fail(Failure::Io, "terminal UI failed", e));
 
-            #[cfg(feature = "debug")]
-            interpret(&toks, &mut stdin, &mut file, args.debug_width.unwrap_or(8));
+    let mut stdout = output(args);
+    stdout
+        .write_all(tui.output())
+        .and_then(|()| stdout.flush())
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to write to output", e));
+}
+
+/// Runs every golden-output test in a directory, exiting with an error if any
+/// fail.
+fn test(args: &Args, files: &[String]) {
+    let [dir] = files else {
+        err("invalid arguments", "`test` takes exactly one directory");
+    };
+
+    let cases = golden::find(Path::new(dir))
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to find tests", e));
+    if cases.is_empty() {
+        err("no tests found", dir);
+    }
+
+    let mut failed = 0;
+    for case in &cases {
+        let name = case.program.strip_prefix(dir).unwrap_or(&case.program);
+
+        match run_test(args, case) {
+            Ok(()) => println!("PASS {}", name.display()),
+            Err(why) => {
+                println!("FAIL {}: {}", name.display(), why.trim_end());
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "{} tests: {} passed, {failed} failed",
+        cases.len(),
+        cases.len() - failed
+    );
+
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+/// Runs a single golden-output test, within the `--max-steps` and `--timeout`
+/// limits (by default, 10 seconds). Returns why it failed, if it did.
+fn run_test(args: &Args, case: &Case) -> Result<(), String> {
+    let read = |path: &Path| {
+        fs::read(path).map_err(|e| format!("failed to read `{}`: {e}", path.display()))
+    };
+
+    let input = case.input.as_deref().map(read).transpose()?;
+    let expected = read(&case.output)?;
+
+    let program = decode(args, &case.program.to_string_lossy(), read(&case.program)?)?;
+    let mut machine = new_machine(args, &program);
+    configure_dump(args, &program, &mut machine, None);
+
+    let timeout = timeout(args).unwrap_or(Duration::from_secs(10));
+    let mut limits = Limits::new(args.max_steps, Some(timeout));
+    let mut output = Vec::new();
+
+    let state = machine.run_with(
+        &mut limits,
+        &mut program
+            .input
+            .iter()
+            .copied()
+            .chain(input.unwrap_or_default()),
+        &mut output,
+    );
+
+    if let Some(exceeded) = limits.exceeded {
+        return Err(exceeded.to_string());
+    }
+
+    if state == State::Hanging {
+        return Err("hangs".to_string());
+    }
+
+    golden::diff(&expected, &output).map_or(Ok(()), Err)
+}
+
+/// Gets the `--timeout`, if any.
+fn timeout(args: &Args) -> Option<Duration> {
+    args.timeout
+        .map(|secs| Duration::try_from_secs_f64(secs).unwrap_or_else(|e| err("invalid timeout", e)))
+}
+
+/// The default workload for `bench`.
+const MANDELBROT: &str = include_str!("../mandelbrot.bf");
+
+/// Times a program (by default, `mandelbrot.bf`) over several runs. With
+/// `--compare`, it's also timed unoptimized, and with the JIT if enabled.
+fn bench(args: &Args, files: &[String]) {
+    let (name, program) = match files {
+        [] => (
+            "mandelbrot.bf",
+            parse(args, "mandelbrot.bf", MANDELBROT.to_string()),
+        ),
+        [filename] => (filename.as_str(), load(args, filename)),
+        _ => err("invalid arguments", "`bench` takes at most one file"),
+    };
+
+    let runs = args.runs.unwrap_or(5);
+    let warmup = args.warmup.unwrap_or(1);
+    if runs == 0 {
+        err("invalid arguments", "`--runs` must be at least 1");
+    }
+
+    let file = args
+        .input
+        .as_ref()
+        .map(|i| fs::read(i).unwrap_or_else(|e| fail(Failure::Io, "failed to read input file", e)))
+        .unwrap_or_default();
+    let input = [program.input.as_slice(), &file].concat();
+
+    let interpret = |code: &[Token]| {
+        let mut machine = Machine::new(code);
+        machine.dump.out = Some(Box::new(io::sink()));
+
+        if machine.run(&mut input.iter().copied(), &mut io::sink()) == State::Hanging {
+            err("failed to benchmark", format!("`{name}` hangs"));
         }
+
+        machine.steps
+    };
+
+    println!("{name}: {runs} runs, {warmup} warmup");
+
+    let optimized = bench::measure(runs, warmup, || interpret(&program.code));
+    println!("optimized  {optimized}");
+
+    if !args.compare {
+        return;
+    }
+
+    if program.source.is_empty() {
+        warn("no unoptimized comparison: the program has no source code");
     } else {
-        for filename in files.iter().skip(1) {
-            let input = fs::read_to_string(filename).unwrap_or_else(|e| err("failed", e));
+        let code = token::match_brackets(&token::parse_with(&program.source, &program.options));
+        let stats = bench::measure(runs, warmup, || interpret(&code));
+        println!("-O0        {stats}");
+    }
+
+    #[cfg(feature = "jit")]
+    if let Some(prog) = brim::jit::compile(&program.code) {
+        let stats = bench::measure(runs, warmup, || {
+            prog.run(&mut input.iter().copied(), &mut io::sink());
+            optimized.steps
+        });
+        println!("jit        {stats}");
+    } else {
+        warn("no JIT comparison: the program isn't supported");
+    }
+}
+
+/// Compiles a program into bytecode.
+fn compile(args: &Args, files: &[String]) {
+    let [filename] = files else {
+        err("invalid arguments", "`compile` takes exactly one file");
+    };
+
+    let program = load(args, filename);
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| output_name(filename, "bfc"));
+
+    fs::write(output, bytecode::serialize(&program.code))
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to write bytecode", e));
+}
+
+/// Compiles a program into a standalone executable.
+fn build(args: &Args, files: &[String]) {
+    let [filename] = files else {
+        err("invalid arguments", "`build` takes exactly one file");
+    };
+
+    let program = load(args, filename);
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| output_name(filename, ""));
+
+    fs::write(&output, brim::emit::elf::emit(&program.code))
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to write executable", e));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(&output, fs::Permissions::from_mode(0o755))
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to make executable", e));
+    }
+}
+
+/// Translates a program into the given format.
+fn emit(args: &Args, format: &str, toks: &[Token]) -> String {
+    match format {
+        "asm" => brim::emit::asm::emit(toks),
+        "bf" => {
+            let debug_char = parse_options(args).debug_chars.first().copied();
+            brim::emit::bf::emit(toks, args.width, debug_char)
+        }
+        "ir" => brim::emit::ir::emit(toks),
+        "llvm" => brim::emit::llvm::emit(toks),
+        "wat" => brim::emit::wat::emit(toks),
+        _ => err("invalid emit format", format),
+    }
+}
+
+/// Runs a program, using the JIT if it's enabled and supported. If any
+/// instrumentation or limit is enabled, or the tape matters beyond the run, the
+/// program is interpreted step by step on `machine` instead. Exits if the
+/// program runs past `--max-steps` or `--timeout`.
+fn run<'a>(
+    args: &Args,
+    filename: &str,
+    program: &'a Program,
+    machine: &mut Machine<'a>,
+    stdin: &mut impl Iterator<Item = u8>,
+    stdout: &mut impl Write,
+    reports: &mut Reports,
+) {
+    let watch = args.watch.as_ref().map(|cells| {
+        let mut watch = Watch::new(false);
+        for cells in cells {
+            let (start, end) =
+                parse_range(cells).unwrap_or_else(|| err("invalid watch range", cells));
+            watch.ranges.push(start..end);
+        }
+
+        WatchLog { watch, program }
+    });
+    let profile = (args.profile || reports.coverage.is_some()).then(|| Profile::new(&program.code));
+    let trace = reports
+        .trace
+        .as_mut()
+        .map(|out| Trace::new(program, out, &reports.trace_filters));
+
+    configure_dump(args, program, machine, reports.dump.as_ref());
+
+    let timeout = timeout(args);
+    let mut limits = (args.max_steps.is_some() || timeout.is_some())
+        .then(|| Limits::new(args.max_steps, timeout));
+
+    if watch.is_none() && profile.is_none() && trace.is_none() {
+        #[cfg(feature = "jit")]
+        if reports.tape.is_none()
+            && args.tape_init.is_none()
+            && args.tape_pointer.is_none()
+            && limits.is_none()
+            && args.exit_cell.is_none()
+            && !args.exit_code
+            && !args.link
+        {
+            if let Some(prog) = brim::jit::compile(&program.code) {
+                prog.run(stdin, stdout);
+                return;
+            }
+        }
+
+        let state = observe(machine, &mut limits, &mut (), stdin, stdout);
+        save_tape(args, machine, reports, state, limits.as_ref());
+        stop(args, limits.as_ref(), state);
+
+        return;
+    }
+
+    let mut observer = (watch, (profile, trace));
+    let state = observe(machine, &mut limits, &mut observer, stdin, stdout);
+    let (profile, trace) = observer.1;
+
+    if let Some(trace) = trace {
+        trace
+            .finish()
+            .unwrap_or_else(|e| fail(Failure::Io, "failed to write trace", e));
+    }
+
+    if let Some(profile) = profile {
+        if args.profile {
+            eprint!("{}", profile.report(program));
+        }
+
+        if let Some(file) = &mut reports.coverage {
+            write_coverage(args, filename, program, &profile, file);
+        }
+    }
+
+    save_tape(args, machine, reports, state, limits.as_ref());
+    stop(args, limits.as_ref(), state);
+}
+
+/// Exits if a program ran into `limits`, or waits forever if it hangs. If it
+/// hangs while limited, it would run into one of them eventually, so it exits
+/// as if it had.
+fn stop(args: &Args, limits: Option<&Limits>, state: State) {
+    match limits.map(|limits| limits.exceeded) {
+        Some(Some(exceeded @ Exceeded::Steps(_))) => {
+            fail(Failure::StepLimit, "program stopped", exceeded)
+        }
+        Some(Some(exceeded @ Exceeded::Time(_))) => {
+            fail(Failure::Timeout, "program stopped", exceeded)
+        }
+        Some(None) if state == State::Hanging => {
+            let failure = if args.max_steps.is_some() {
+                Failure::StepLimit
+            } else {
+                Failure::Timeout
+            };
+
+            fail(failure, "program stopped", "it hangs")
+        }
+        None if state == State::Hanging => hang(),
+        _ => {}
+    }
+}
 
-            let toks = parse(&input);
-            let toks = optimize(&toks);
+/// Writes a coverage report for a program: lcov if the `--coverage` file ends
+/// in `.info` or `.lcov`, otherwise an annotated copy of the source.
+fn write_coverage(
+    args: &Args,
+    filename: &str,
+    program: &Program,
+    profile: &Profile,
+    file: &mut File,
+) {
+    if program.spans.is_empty() && !program.code.is_empty() {
+        warn(format!(
+            "no coverage for `{filename}`: it has no source code"
+        ));
+        return;
+    }
+
+    let ext = args
+        .coverage
+        .as_deref()
+        .and_then(|path| Path::new(path).extension())
+        .and_then(|ext| ext.to_str());
+
+    let report = match ext {
+        Some("info" | "lcov") => coverage::lcov(program, profile, filename),
+        _ => coverage::annotate(program, profile, filename),
+    };
 
-            #[cfg(not(feature = "debug"))]
-            interpret(&toks, &mut stdin, &mut stdout());
+    file.write_all(report.as_bytes())
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to write coverage", e));
+}
+
+/// Runs a program step by step on `machine` under `observer`, like
+/// [`interpret`](brim::interpret), until it finishes, hangs, or runs into
+/// `limits` (if any).
+fn observe(
+    machine: &mut Machine,
+    limits: &mut Option<Limits>,
+    observer: &mut impl Observer,
+    stdin: &mut impl Iterator<Item = u8>,
+    stdout: &mut impl Write,
+) -> State {
+    let state = loop {
+        // Only check the limits when they're set, since it slows every step.
+        let state = match limits {
+            Some(limits) => machine.run_with(&mut (&mut *limits, &mut *observer), stdin, stdout),
+            None => machine.run_with(observer, stdin, stdout),
+        };
 
-            #[cfg(feature = "debug")]
-            interpret(&toks, &mut stdin, &mut stdout(), args.debug_width.unwrap_or(8));
+        let exceeded = limits.as_ref().is_some_and(|l| l.exceeded.is_some());
+        if state != State::Stopped || exceeded {
+            break state;
         }
+    };
+
+    stdout
+        .flush()
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to flush stdout", e));
+
+    state
+}
+
+/// Saves the tape to the `--dump-tape` file, if given. With `--link`, it's only
+/// saved after the last program, unless an earlier one stops brim by hanging or
+/// running into `limits`.
+fn save_tape(
+    args: &Args,
+    machine: &Machine,
+    reports: &Reports,
+    state: State,
+    limits: Option<&Limits>,
+) {
+    let stopped = state == State::Hanging || limits.is_some_and(|l| l.exceeded.is_some());
+
+    if let Some(file) = &reports.tape {
+        if reports.last || stopped {
+            write_tape(args, machine, file);
+        }
+    }
+}
+
+/// Saves a machine's tape: as JSON if the `--dump-tape` file ends in `.json`,
+/// otherwise in binary.
+fn write_tape(args: &Args, machine: &Machine, mut file: &File) {
+    let ext = args
+        .dump_tape
+        .as_deref()
+        .and_then(|path| Path::new(path).extension())
+        .and_then(|ext| ext.to_str());
+
+    let bytes = match ext {
+        Some("json") => tape::json(machine).into_bytes(),
+        _ => tape::raw(machine),
+    };
+
+    file.write_all(&bytes)
+        .unwrap_or_else(|e| fail(Failure::Io, "failed to write tape", e));
+}
+
+/// Waits forever, for a program that hangs.
+fn hang() -> ! {
+    loop {
+        std::thread::sleep(std::time::Duration::new(1000000, 0));
+    }
+}
+
+/// Logs changes to watched cells to stderr as they happen.
+struct WatchLog<'a> {
+    watch: Watch,
+    program: &'a Program,
+}
+
+impl Observer for WatchLog<'_> {
+    fn before(&mut self, machine: &Machine) -> bool {
+        self.watch.before(machine)
+    }
+
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        let keep_going = self.watch.after(machine, ip);
+
+        for hit in self.watch.hits.drain(..) {
+            eprintln!("watch: {}", hit.describe(self.program));
+        }
+
+        keep_going
     }
 }
@@ -0,0 +1,192 @@
+//! An execution profiler, for finding where programs spend their time.
+
+use std::fmt::Write;
+
+use crate::machine::{Machine, Observer};
+use crate::program::Program;
+use crate::token::Token;
+
+/// How many loops to include in a [`Profile::report`].
+const REPORT_LOOPS: usize = 20;
+/// How much of the total runtime an unoptimized loop must take up before it's
+/// flagged as hot.
+const HOT_SHARE: f64 = 0.01;
+
+/// Execution statistics for a single loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoopStats {
+    /// The index of the opening bracket.
+    pub start: usize,
+    /// The index of the closing bracket.
+    pub end: usize,
+    /// How many times the loop body ran.
+    pub iterations: u64,
+    /// How many tokens were run inside the loop, brackets included.
+    pub steps: u64,
+    /// Whether the loop contains no other loops, making it a candidate for a
+    /// macro-token.
+    pub innermost: bool,
+}
+
+/// Counts how many times each token runs.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// How many times each token ran, by index.
+    pub counts: Vec<u64>,
+}
+
+impl Profile {
+    /// Creates an empty profile for `code`.
+    pub fn new(code: &[Token]) -> Self {
+        Self {
+            counts: vec![0; code.len()],
+        }
+    }
+
+    /// The total number of tokens run.
+    pub fn steps(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Gathers statistics for every loop in `code`, most iterations first.
+    pub fn loops(&self, code: &[Token]) -> Vec<LoopStats> {
+        let mut loops: Vec<_> = code
+            .iter()
+            .enumerate()
+            .filter_map(|(start, tok)| match *tok {
+                Token::LBrack(end) => Some(LoopStats {
+                    start,
+                    end,
+                    // Every iteration ends by running the closing bracket.
+                    iterations: self.counts[end],
+                    steps: self.counts[start..=end].iter().sum(),
+                    innermost: !code[start + 1..end]
+                        .iter()
+                        .any(|tok| matches!(tok, Token::LBrack(_))),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        loops.sort_by(|a, b| b.iterations.cmp(&a.iterations).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    /// Formats a report of the hottest loops in `program`, flagging those
+    /// that take up a lot of time but weren't turned into macro-tokens.
+    pub fn report(&self, program: &Program) -> String {
+        let total = self.steps();
+        let mut out = format!("profile: {total} steps\n");
+
+        let loops = self.loops(&program.code);
+        if loops.is_empty() {
+            out.push_str("no loops\n");
+            return out;
+        }
+
+        let mut hot = 0;
+        out.push_str("  iterations        steps   share  location  loop\n");
+        for stats in loops.iter().take(REPORT_LOOPS) {
+            let share = if total == 0 {
+                0.0
+            } else {
+                stats.steps as f64 / total as f64
+            };
+            // A loop that was skipped every time only counts its opening bracket.
+            let flag = if stats.innermost && stats.iterations > 0 && share >= HOT_SHARE {
+                hot += 1;
+                '!'
+            } else {
+                ' '
+            };
+
+            let location = match program.position(stats.start) {
+                Some((line, col)) => format!("{line}:{col}"),
+                None => format!("#{}", stats.start),
+            };
+
+            let _ = writeln!(
+                out,
+                "{flag}{:>11}  {:>11}  {:>5.1}%  {location:<8}  {}",
+                stats.iterations,
+                stats.steps,
+                share * 100.0,
+                loop_text(program, stats.start, stats.end),
+            );
+        }
+
+        if loops.len() > REPORT_LOOPS {
+            let _ = writeln!(out, "  ... and {} more", loops.len() - REPORT_LOOPS);
+        }
+
+        if hot != 0 {
+            out.push_str(
+                "! = hot innermost loop that wasn't optimized into a macro-token; \
+                 consider rewriting it\n",
+            );
+        }
+
+        out
+    }
+}
+
+impl Observer for Profile {
+    fn before(&mut self, machine: &Machine) -> bool {
+        self.counts[machine.ip] += 1;
+        true
+    }
+}
+
+/// The source of a loop, without comments, shortened if it's long.
+fn loop_text(program: &Program, start: usize, end: usize) -> String {
+    const MAX: usize = 40;
+
+    let text: String = match (program.spans.get(start), program.spans.get(end)) {
+        (Some(start), Some(end)) => program.source[start.start..end.end]
+            .chars()
+            .filter(|&c| program.options.is_command(c))
+            .collect(),
+        // Without the source, the optimized tokens are the next best thing.
+        _ => program.code[start..=end]
+            .iter()
+            .map(Token::to_string)
+            .collect(),
+    };
+
+    if text.chars().count() > MAX {
+        let mut text: String = text.chars().take(MAX - 3).collect();
+        text.push_str("...");
+        text
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Profiles `source`, returning its report.
+    fn report(source: &str) -> String {
+        let program = Program::new(source.to_string());
+        let mut profile = Profile::new(&program.code);
+        Machine::new(&program.code).run_with(
+            &mut profile,
+            &mut std::iter::empty(),
+            &mut std::io::sink(),
+        );
+        profile.report(&program)
+    }
+
+    #[test]
+    fn skipped_loop_is_not_hot() {
+        let out = report("<[+++.]");
+        assert!(!out.contains('!'), "{out}");
+    }
+
+    #[test]
+    fn busy_loop_is_hot() {
+        let out = report("+++[>+++.<-]");
+        assert!(out.contains("!          3"), "{out}");
+    }
+}
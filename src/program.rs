@@ -0,0 +1,102 @@
+//! A program, alongside where each of its tokens came from.
+
+use crate::emit::ir::mnemonic;
+use crate::helper::line_col;
+use crate::token::{optimize_spanned, parse_spanned_with, ParseOptions, Span, Token};
+
+/// An optimized program, which remembers its source code.
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    /// The original source code. Empty if unknown, e.g. for bytecode.
+    pub source: String,
+    /// The optimized tokens.
+    pub code: Vec<Token>,
+    /// The span of source code each token came from. Empty if the source is
+    /// unknown.
+    pub spans: Vec<Span>,
+    /// The options the source was parsed with.
+    pub options: ParseOptions,
+    /// Input data embedded in the source after the
+    /// [`input_separator`](ParseOptions::input_separator), if any.
+    pub input: Vec<u8>,
+}
+
+impl Program {
+    /// Parses and optimizes `source`.
+    pub fn new(source: String) -> Self {
+        Self::with_options(source, ParseOptions::default())
+    }
+
+    /// Parses and optimizes `source` with custom [`ParseOptions`].
+    pub fn with_options(source: String, options: ParseOptions) -> Self {
+        let (code, spans) = parse_spanned_with(&source, &options);
+        let (code, spans) = optimize_spanned(&code, &spans);
+        let input = options
+            .split_input(&source)
+            .1
+            .map(|input| input.as_bytes().to_vec())
+            .unwrap_or_default();
+
+        Self {
+            source,
+            code,
+            spans,
+            options,
+            input,
+        }
+    }
+
+    /// Wraps already-optimized tokens whose source is unknown.
+    pub fn from_tokens(code: Vec<Token>) -> Self {
+        Self {
+            code,
+            ..Self::default()
+        }
+    }
+
+    /// The 1-based line and column token `i` starts at, if known.
+    pub fn position(&self, i: usize) -> Option<(usize, usize)> {
+        self.spans
+            .get(i)
+            .map(|span| line_col(&self.source, span.start))
+    }
+
+    /// The 1-based line and column each token starts at. Faster than calling
+    /// [`position`](Self::position) for every token. Empty if the source is
+    /// unknown.
+    pub fn positions(&self) -> Vec<(usize, usize)> {
+        let mut positions = Vec::with_capacity(self.spans.len());
+        let (mut line, mut col, mut offset) = (1, 1, 0);
+
+        for span in &self.spans {
+            for ch in self.source[offset..span.start].chars() {
+                if ch == '\n' {
+                    line += 1;
+                    col = 1;
+                } else {
+                    col += 1;
+                }
+            }
+
+            offset = span.start;
+            positions.push((line, col));
+        }
+
+        positions
+    }
+
+    /// The source code token `i` came from, if known.
+    pub fn text(&self, i: usize) -> Option<&str> {
+        self.spans
+            .get(i)
+            .map(|span| &self.source[span.start..span.end])
+    }
+
+    /// Describes token `i`, e.g. `#12 (3:5) add [+1] *1`.
+    pub fn describe(&self, i: usize) -> String {
+        match self.position(i) {
+            Some((line, col)) => format!("#{i} ({line}:{col}) {}", mnemonic(&self.code[i])),
+            None => format!("#{i} {}", mnemonic(&self.code[i])),
+        }
+    }
+}
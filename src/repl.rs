@@ -0,0 +1,197 @@
+//! An interactive REPL, which runs each line of brain* against the same tape.
+//!
+//! Lines starting with `:` are commands. Run `:help` inside the REPL for a
+//! list.
+
+use std::fs;
+use std::io::{self, Write};
+
+use crate::debugger::print_cells;
+use crate::helper::parse_range;
+use crate::machine::{Machine, State};
+use crate::program::Program;
+use crate::token::ParseOptions;
+use crate::Cell;
+
+const HELP: &str = "\
+commands:
+    :tape [cells]      print the cells around the pointer, a cell (`37`), or a
+                       range of cells (`30..40`)
+    :reset             clear the tape and move the pointer back to 0
+    :load <file>       run a file against the tape
+    :help              show this message
+    :quit         (:q) exit the REPL
+anything else is run as brain*, continuing onto more lines while brackets are
+unbalanced";
+
+/// A REPL session, whose tape persists from one line to the next.
+pub struct Repl {
+    /// The tape.
+    pub tape: Vec<Cell>,
+    /// The tape pointer.
+    pub sp: usize,
+    options: ParseOptions,
+    /// Code entered so far, while its brackets are unbalanced.
+    pending: String,
+}
+
+impl Repl {
+    /// Creates a REPL with an empty tape, which parses code with `options`.
+    pub fn new(options: ParseOptions) -> Self {
+        Self {
+            tape: Machine::new(&[]).tape,
+            sp: 0,
+            options,
+            pending: String::new(),
+        }
+    }
+
+    /// Runs the REPL until `lines` (e.g.
+    /// [`BufRead::lines`](std::io::BufRead::lines)) runs out, or the user
+    /// quits. Programs read from `stdin` and write to `stdout`, while prompts
+    /// and the tape are written to `ui`.
+    pub fn run(
+        &mut self,
+        lines: &mut impl Iterator<Item = io::Result<String>>,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+        ui: &mut impl Write,
+    ) -> io::Result<()> {
+        loop {
+            let prompt = if self.pending.is_empty() {
+                "brim> "
+            } else {
+                "...   "
+            };
+            write!(ui, "{prompt}")?;
+            ui.flush()?;
+
+            let Some(line) = lines.next().transpose()? else {
+                writeln!(ui)?;
+                return Ok(());
+            };
+
+            if self.pending.is_empty() {
+                if let Some(command) = line.trim().strip_prefix(':') {
+                    if !self.command(command, stdin, stdout, ui)? {
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+            }
+
+            self.pending.push_str(line.trim_end_matches('\n'));
+            self.pending.push('\n');
+
+            match self.depth(&self.pending) {
+                Some(0) => {
+                    let source = std::mem::take(&mut self.pending);
+                    if !source.trim().is_empty() {
+                        self.exec(source, stdin, stdout, ui)?;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    writeln!(ui, "unmatched closing bracket")?;
+                    self.pending.clear();
+                }
+            }
+        }
+    }
+
+    /// Runs a single command, without its `:`. Returns false if the user quit.
+    fn command(
+        &mut self,
+        line: &str,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+        ui: &mut impl Write,
+    ) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+
+        match (cmd, args.as_slice()) {
+            ("tape", []) => self.print_tape(ui)?,
+            ("tape", [cells]) => match parse_range(cells) {
+                Some((start, end)) => print_cells(&self.tape, self.sp, start, end, ui)?,
+                None => writeln!(ui, "invalid cell or range `{cells}`")?,
+            },
+            ("reset", []) => {
+                *self = Self::new(std::mem::take(&mut self.options));
+                self.print_tape(ui)?;
+            }
+            ("load", [file]) => match fs::read_to_string(file) {
+                Ok(source) if self.depth(&source) == Some(0) => {
+                    self.exec(source, stdin, stdout, ui)?
+                }
+                Ok(_) => writeln!(ui, "`{file}` has unbalanced brackets")?,
+                Err(e) => writeln!(ui, "failed to read `{file}`: {e}")?,
+            },
+            ("help", []) => writeln!(ui, "{HELP}")?,
+            ("q" | "quit", []) => return Ok(false),
+            _ => writeln!(ui, "unknown command `:{line}` (try `:help`)")?,
+        }
+
+        Ok(true)
+    }
+
+    /// Runs `source` against the tape, then prints the cells around the
+    /// pointer.
+    fn exec(
+        &mut self,
+        source: String,
+        stdin: &mut impl Iterator<Item = u8>,
+        stdout: &mut impl Write,
+        ui: &mut impl Write,
+    ) -> io::Result<()> {
+        let program = Program::with_options(source, self.options.clone());
+
+        let mut machine = Machine::new(&program.code);
+        machine.dump.program = Some(&program);
+        machine.tape = std::mem::take(&mut self.tape);
+        machine.sp = self.sp;
+
+        // Input embedded in the line comes first.
+        let state = machine.run(&mut program.input.iter().copied().chain(stdin), stdout);
+        stdout.flush()?;
+
+        self.tape = machine.tape;
+        self.sp = machine.sp;
+
+        if state == State::Hanging {
+            writeln!(ui, "the program hangs, so it was stopped")?;
+        }
+
+        self.print_tape(ui)
+    }
+
+    /// Counts the brackets left open at the end of `source`, or returns `None`
+    /// if one is closed without being opened. Brackets in any input after the
+    /// separator don't count.
+    fn depth(&self, source: &str) -> Option<usize> {
+        let mut depth = 0usize;
+
+        for ch in self.options.split_input(source).0.chars() {
+            match ch {
+                '[' => depth += 1,
+                ']' => depth = depth.checked_sub(1)?,
+                _ => {}
+            }
+        }
+
+        Some(depth)
+    }
+
+    /// Prints the cells around the pointer.
+    fn print_tape(&self, ui: &mut impl Write) -> io::Result<()> {
+        print_cells(
+            &self.tape,
+            self.sp,
+            self.sp.saturating_sub(8),
+            self.sp + 8,
+            ui,
+        )
+    }
+}
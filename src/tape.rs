@@ -0,0 +1,189 @@
+//! Saving a machine's tape, e.g. to check the state a program left behind, and
+//! loading one to start a program with.
+//!
+//! A tape is saved either as a line of JSON, or in a raw binary format. Either
+//! way, trailing zero cells are trimmed, and the cell width and signedness are
+//! recorded alongside the cells, tape pointer and step count.
+//!
+//! A tape can be loaded from raw bytes (one per cell), a saved binary tape, or
+//! a list of cell values: either JSON (including a saved JSON tape) or CSV.
+//!
+//! Binary layout (all integers little-endian):
+//!
+//! - magic: `b"BRIMTP"`
+//! - version: `u16`
+//! - cell width in bytes: `u8`
+//! - flags: `u8` (bit 0: signed cells)
+//! - tape pointer: `u64`
+//! - steps: `u64`
+//! - cell count: `u64`
+//! - cells, at cell width
+
+use std::fmt::{Display, Write};
+use std::mem::size_of;
+
+use crate::bytecode::Config;
+use crate::machine::Machine;
+use crate::Cell;
+
+/// Identifies a binary tape file.
+pub const MAGIC: &[u8; 6] = b"BRIMTP";
+
+/// The current version of the binary format.
+pub const VERSION: u16 = 1;
+
+/// An error encountered while loading a tape.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A binary tape was written by an unsupported version of the format.
+    Version(u16),
+    /// A binary tape was saved with different cells than this build uses.
+    Mismatch { cell_width: u8, signed: bool },
+    /// A binary tape ended early.
+    Truncated,
+    /// A value in a list isn't a valid cell.
+    Value(String),
+    /// The tape (or the tape pointer) needs more cells than there are.
+    TooLong { len: usize, max: usize },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Version(v) => write!(f, "unsupported version {v} (expected {VERSION})"),
+            Error::Mismatch { cell_width, signed } => {
+                let config = Config::current();
+                write!(
+                    f,
+                    "saved with {}-bit {} cells, but this build uses {}-bit {} cells",
+                    *cell_width as u32 * 8,
+                    if *signed { "signed" } else { "unsigned" },
+                    config.cell_width as u32 * 8,
+                    if config.signed { "signed" } else { "unsigned" },
+                )
+            }
+            Error::Truncated => write!(f, "unexpected end of file"),
+            Error::Value(v) => write!(f, "invalid cell value `{v}`"),
+            Error::TooLong { len, max } => {
+                write!(f, "needs {len} cells, but the tape only has {max}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Loads cells from raw bytes, one per cell, or from a binary tape if `bytes`
+/// starts with [`MAGIC`].
+pub fn from_bytes(bytes: &[u8]) -> Result<Vec<Cell>, Error> {
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        return Ok(bytes.iter().map(|&b| b as Cell).collect());
+    };
+
+    let mut fields = rest;
+    let mut take = |n: usize| {
+        if fields.len() < n {
+            return Err(Error::Truncated);
+        }
+
+        let (field, rest) = fields.split_at(n);
+        fields = rest;
+        Ok(field)
+    };
+
+    let version = u16::from_le_bytes(take(2)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(Error::Version(version));
+    }
+
+    let cell_width = take(1)?[0];
+    let signed = take(1)?[0] & 1 != 0;
+    let config = Config::current();
+    if cell_width != config.cell_width || signed != config.signed {
+        return Err(Error::Mismatch { cell_width, signed });
+    }
+
+    // The tape pointer and steps.
+    take(16)?;
+
+    let len = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+    let cells = take(len.checked_mul(size_of::<Cell>()).ok_or(Error::Truncated)?)?;
+
+    Ok(cells
+        .chunks_exact(size_of::<Cell>())
+        .map(|cell| Cell::from_le_bytes(cell.try_into().unwrap()))
+        .collect())
+}
+
+/// Loads cells from a list of values, separated by commas or whitespace, e.g.
+/// a JSON list or CSV. A saved JSON tape's cells are loaded too.
+pub fn from_list(text: &str) -> Result<Vec<Cell>, Error> {
+    let mut list = text.trim();
+
+    if list.starts_with('{') {
+        let start = list
+            .find("\"cells\"")
+            .ok_or(Error::Value(list.to_string()))?;
+        list = &list[start + "\"cells\"".len()..];
+        list = list.trim_start().strip_prefix(':').unwrap_or(list);
+        list = &list[..list.find(']').map_or(list.len(), |i| i + 1)];
+    }
+
+    list.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(|ch: char| ch == ',' || ch.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse().map_err(|_| Error::Value(value.to_string())))
+        .collect()
+}
+
+/// Serializes a machine's tape in the binary format.
+pub fn raw(machine: &Machine) -> Vec<u8> {
+    let config = Config::current();
+    let cells = trimmed(&machine.tape);
+    let mut out = Vec::new();
+
+    out.extend(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.push(config.cell_width);
+    out.push(config.signed as u8);
+    out.extend((machine.sp as u64).to_le_bytes());
+    out.extend(machine.steps.to_le_bytes());
+    out.extend((cells.len() as u64).to_le_bytes());
+
+    for cell in cells {
+        out.extend(cell.to_le_bytes());
+    }
+
+    out
+}
+
+/// Serializes a machine's tape as a line of JSON.
+pub fn json(machine: &Machine) -> String {
+    let config = Config::current();
+    let mut out = format!(
+        "{{\"cell_width\":{},\"signed\":{},\"sp\":{},\"steps\":{},\"cells\":[",
+        config.cell_width, config.signed, machine.sp, machine.steps
+    );
+
+    for (i, cell) in trimmed(&machine.tape).iter().enumerate() {
+        if i != 0 {
+            out.push(',');
+        }
+
+        let _ = write!(out, "{cell}");
+    }
+
+    out.push_str("]}\n");
+    out
+}
+
+/// Trims the zero cells off the end of a tape.
+fn trimmed(tape: &[Cell]) -> &[Cell] {
+    let len = tape
+        .iter()
+        .rposition(|&cell| cell != 0)
+        .map_or(0, |i| i + 1);
+    &tape[..len]
+}
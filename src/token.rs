@@ -1,6 +1,7 @@
 use std::fmt::Display;
 
-use crate::{err, helper::left_right, Cell};
+use crate::helper::{fail, left_right, Failure};
+use crate::Cell;
 
 /// An optimized token; it may represent more than one brain* instruction.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -44,12 +45,61 @@ pub enum Token {
     /// Macro-optimization. Equivalent to `[]`.
     End,
 
-    /// Equivalent to `';'`. Only available if in debug mode, or if feature
-    /// flag `debug` is enabled.
-    #[cfg(any(debug_assertions, feature = "debug"))]
+    /// Prints debug info. Parsed from any of
+    /// [`ParseOptions::debug_chars`]: by default, `;` if in debug mode or if
+    /// feature flag `debug` is enabled.
     Dump,
 }
 
+/// A range of bytes in the source code, `start..end`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Options for [`parse_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The characters parsed as [`Dump`](Token::Dump), e.g. `;` or `#`. By
+    /// default, `;` if in debug mode or if feature flag `debug` is enabled,
+    /// and none otherwise.
+    pub debug_chars: Vec<char>,
+    /// If given, the source stops at the first of this character, e.g. `!`,
+    /// and everything after it is input data for the program. Off by default.
+    pub input_separator: Option<char>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            debug_chars: if cfg!(any(debug_assertions, feature = "debug")) {
+                vec![';']
+            } else {
+                Vec::new()
+            },
+            input_separator: None,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Returns true if `ch` is a brain* instruction, rather than a comment.
+    pub fn is_command(&self, ch: char) -> bool {
+        matches!(ch, '+' | '-' | '>' | '<' | '[' | ']' | '.' | ',')
+            || self.debug_chars.contains(&ch)
+    }
+
+    /// Splits `source` at the [`input_separator`](Self::input_separator) into
+    /// the program and its input data, if there's a separator.
+    pub fn split_input<'a>(&self, source: &'a str) -> (&'a str, Option<&'a str>) {
+        match self.input_separator.and_then(|sep| source.split_once(sep)) {
+            Some((code, input)) => (code, Some(input)),
+            None => (source, None),
+        }
+    }
+}
+
 /// Parse brain* input into [`Token`]s. Groups together [`Inc`](Token::Inc) and
 /// [`Dec`](Token::Dec) instructions, as well as converting `<` and `>` to
 /// [`Goto`](Token::Goto).
@@ -58,10 +108,29 @@ pub enum Token {
 /// and [`RBrack`](Token::RBrack) still needs to be set to its match, i.e. via
 /// [`optimize`].
 pub fn parse(input: &str) -> Vec<Token> {
-    // let input = input.to_string();
+    parse_with(input, &ParseOptions::default())
+}
+
+/// Like [`parse`], but with custom [`ParseOptions`].
+pub fn parse_with(input: &str, options: &ParseOptions) -> Vec<Token> {
+    parse_spanned_with(input, options).0
+}
+
+/// Like [`parse`], but also returns the [`Span`] of source code each token
+/// came from.
+pub fn parse_spanned(input: &str) -> (Vec<Token>, Vec<Span>) {
+    parse_spanned_with(input, &ParseOptions::default())
+}
+
+/// Like [`parse_spanned`], but with custom [`ParseOptions`].
+pub fn parse_spanned_with(input: &str, options: &ParseOptions) -> (Vec<Token>, Vec<Span>) {
+    let (input, _) = options.split_input(input);
     let mut toks = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+
+    for (i, ch) in input.char_indices() {
+        let len = toks.len();
 
-    for ch in input.chars() {
         match ch {
             '+' => {
                 if let Some(&Token::Inc(i)) = toks.last() {
@@ -108,89 +177,225 @@ pub fn parse(input: &str) -> Vec<Token> {
                 toks.push(Token::In);
             }
 
-            #[cfg(any(debug_assertions, feature = "debug"))]
-            ';' => {
+            ch if options.debug_chars.contains(&ch) => {
                 toks.push(Token::Dump);
             }
 
-            _ => {}
+            _ => continue,
+        }
+
+        let end = i + ch.len_utf8();
+        if toks.len() > len {
+            spans.push(Span { start: i, end });
+        } else if let Some(span) = spans.last_mut() {
+            span.end = end;
         }
     }
 
-    toks
+    (toks, spans)
+}
+
+/// Finds a bracket in `input` without a match, returning its byte offset: the
+/// first `]` with nothing to close, or else the last `[` left open. Since
+/// [`optimize`] exits on unmatched brackets, this can be used beforehand to
+/// report where they are.
+pub fn unmatched_bracket(input: &str) -> Option<usize> {
+    let mut lbracks = Vec::new();
+
+    for (i, ch) in input.char_indices() {
+        if ch == '[' {
+            lbracks.push(i);
+        } else if ch == ']' && lbracks.pop().is_none() {
+            return Some(i);
+        }
+    }
+
+    lbracks.pop()
 }
 
 /// Performs macro-optimizations, and sets the final indices for each bracket.
 pub fn optimize(toks: &[Token]) -> Vec<Token> {
+    optimize_mapped(toks).0
+}
+
+/// Sets the final indices for each bracket, without optimizing anything else,
+/// e.g. to measure what [`optimize`] gains.
+pub fn match_brackets(toks: &[Token]) -> Vec<Token> {
+    let mut out = toks.to_vec();
+    let mut lbracks = Vec::new();
+
+    for i in 0..out.len() {
+        match out[i] {
+            Token::LBrack(_) => lbracks.push(i),
+            Token::RBrack(_) => {
+                let lb = lbracks.pop().unwrap_or_else(|| {
+                    fail(Failure::Parse, "invalid input", "unmatched closing bracket")
+                });
+                out[lb] = Token::LBrack(i);
+                out[i] = Token::RBrack(lb);
+            }
+            _ => {}
+        }
+    }
+
+    if !lbracks.is_empty() {
+        fail(Failure::Parse, "invalid input", "unmatched opening bracket");
+    }
+
+    out
+}
+
+/// Like [`optimize`], but also maps each [`Span`] from
+/// [`parse_spanned`] onto the optimized tokens.
+pub fn optimize_spanned(toks: &[Token], spans: &[Span]) -> (Vec<Token>, Vec<Span>) {
+    let (out, ranges) = optimize_mapped(toks);
+
+    let spans = ranges
+        .into_iter()
+        .map(|(start, end)| Span {
+            start: spans[start].start,
+            end: spans[end - 1].end,
+        })
+        .collect();
+
+    (out, spans)
+}
+
+/// Optimizes, and returns the range of input tokens each output token
+/// replaced.
+fn optimize_mapped(toks: &[Token]) -> (Vec<Token>, Vec<(usize, usize)>) {
     let mut out = Vec::new();
+    let mut ranges = Vec::new();
     let mut lbracks = Vec::new();
 
     let mut si = 0;
     while si < toks.len() {
-        let mut tok = toks[si];
+        let start = si;
 
-        if matches!(tok, Token::LBrack(_)) {
-            let next = toks.get(si + 1);
+        'tok: {
+            let mut tok = toks[si];
 
-            // [
-            // Zero / Set / Add / Sub / Dup
-            if matches!(next, Some(Token::Dec(1))) {
-                let next = toks.get(si + 2);
+            if matches!(tok, Token::LBrack(_)) {
+                let next = toks.get(si + 1);
 
-                // [-
-                // Zero / Set
-                if matches!(next, Some(Token::RBrack(_))) {
-                    // [-]
-                    if let Some(Token::Inc(i)) = toks.get(si + 3) {
-                        // [-]+++
-                        out.push(Token::Set(*i));
+                // [
+                // Zero / Set / Add / Sub / Dup
+                if matches!(next, Some(Token::Dec(1))) {
+                    let next = toks.get(si + 2);
 
-                        si += 4;
-                    } else {
-                        out.push(Token::Zero);
+                    // [-
+                    // Zero / Set
+                    if matches!(next, Some(Token::RBrack(_))) {
+                        // [-]
+                        if let Some(Token::Inc(i)) = toks.get(si + 3) {
+                            // [-]+++
+                            out.push(Token::Set(*i));
 
-                        si += 3;
-                    }
+                            si += 4;
+                        } else {
+                            out.push(Token::Zero);
 
-                    continue;
+                            si += 3;
+                        }
+
+                        break 'tok;
+
+                    // [-
+                    // Add / Sub / Dup
+                    } else if let Some(Token::Goto(there)) = next {
+                        let next = toks.get(si + 3);
+
+                        // [->
+                        // Add / Dup
+                        if let Some(Token::Inc(mul)) = next {
+                            if let Some(Token::Goto(back)) = toks.get(si + 4) {
+                                let next = toks.get(si + 5);
+
+                                // [->+ ><
+                                // Add
+                                if *there == -back {
+                                    // [->+<
+                                    if matches!(next, Some(Token::RBrack(_))) {
+                                        // [->+<]
+                                        out.push(Token::Add(*there, *mul));
+
+                                        si += 6;
+                                        break 'tok;
+                                    }
+
+                                // [->+ ><
+                                // Dup
+                                } else if let Some(Token::Inc(mul2)) = next {
+                                    // [->+ >< +
+                                    if let Some(Token::Goto(bi)) = toks.get(si + 6) {
+                                        // [->+ >< + ><
+                                        if bi + there + back == 0
+                                        // [->+>+<<]
+                                        && matches!(toks.get(si + 7), Some(Token::RBrack(_)))
+                                        {
+                                            out.push(Token::Dup(*there, *mul, *back, *mul2));
+
+                                            si += 8;
+                                            break 'tok;
+                                        }
+                                    }
+                                }
+                            }
+
+                        // Sub
+                        } else if matches!(next, Some(Token::Dec(1))) {
+                            if let Some(Token::Goto(back)) = toks.get(si + 4) {
+                                if *there == -back
+                                    && matches!(toks.get(si + 5), Some(Token::RBrack(_)))
+                                {
+                                    out.push(Token::Sub(*there));
+
+                                    si += 6;
+                                    break 'tok;
+                                }
+                            }
+                        }
+                    }
 
-                // [-
-                // Add / Sub / Dup
+                // [
+                // Add / Sub / Dup / Scan
                 } else if let Some(Token::Goto(there)) = next {
-                    let next = toks.get(si + 3);
+                    let next = toks.get(si + 2);
 
-                    // [->
+                    // [>
                     // Add / Dup
                     if let Some(Token::Inc(mul)) = next {
-                        if let Some(Token::Goto(back)) = toks.get(si + 4) {
-                            let next = toks.get(si + 5);
+                        if let Some(Token::Goto(back)) = toks.get(si + 3) {
+                            let next = toks.get(si + 4);
 
-                            // [->+ ><
+                            // [>+ ><
                             // Add
-                            if *there == -back {
-                                // [->+<
-                                if matches!(next, Some(Token::RBrack(_))) {
-                                    // [->+<]
-                                    out.push(Token::Add(*there, *mul));
+                            if *there == -back
+                                && matches!(next, Some(Token::Dec(1)))
+                                && matches!(toks.get(si + 5), Some(Token::RBrack(_)))
+                            {
+                                // [>+<-]
+                                out.push(Token::Add(*there, *mul));
 
-                                    si += 6;
-                                    continue;
-                                }
+                                si += 6;
+                                break 'tok;
 
-                            // [->+ ><
+                            // [>+ ><
                             // Dup
                             } else if let Some(Token::Inc(mul2)) = next {
-                                // [->+ >< +
-                                if let Some(Token::Goto(bi)) = toks.get(si + 6) {
-                                    // [->+ >< + ><
-                                    if bi + there + back == 0
-                                        // [->+>+<<]
-                                        && matches!(toks.get(si + 7), Some(Token::RBrack(_)))
+                                // [>+ >< +
+                                if let Some(Token::Goto(bi)) = toks.get(si + 5) {
+                                    if *bi == -(there + back)
+                                    // [>+>+<<
+                                    && matches!(toks.get(si + 6), Some(Token::Dec(1)))
+                                    // [>+>+<<-
+                                    && matches!(toks.get(si + 7), Some(Token::RBrack(_)))
+                                    // [>+>+<<-]
                                     {
                                         out.push(Token::Dup(*there, *mul, *back, *mul2));
 
                                         si += 8;
-                                        continue;
+                                        break 'tok;
                                     }
                                 }
                             }
@@ -198,120 +403,65 @@ pub fn optimize(toks: &[Token]) -> Vec<Token> {
 
                     // Sub
                     } else if matches!(next, Some(Token::Dec(1))) {
-                        if let Some(Token::Goto(back)) = toks.get(si + 4) {
-                            if *there == -back && matches!(toks.get(si + 5), Some(Token::RBrack(_)))
+                        if let Some(Token::Goto(back)) = toks.get(si + 3) {
+                            if *there == -back
+                                && matches!(toks.get(si + 4), Some(Token::Dec(1)))
+                                && matches!(toks.get(si + 5), Some(Token::RBrack(_)))
                             {
                                 out.push(Token::Sub(*there));
 
                                 si += 6;
-                                continue;
+                                break 'tok;
                             }
                         }
-                    }
-                }
 
-            // [
-            // Add / Sub / Dup / Scan
-            } else if let Some(Token::Goto(there)) = next {
-                let next = toks.get(si + 2);
-
-                // [>
-                // Add / Dup
-                if let Some(Token::Inc(mul)) = next {
-                    if let Some(Token::Goto(back)) = toks.get(si + 3) {
-                        let next = toks.get(si + 4);
-
-                        // [>+ ><
-                        // Add
-                        if *there == -back
-                            && matches!(next, Some(Token::Dec(1)))
-                            && matches!(toks.get(si + 5), Some(Token::RBrack(_)))
-                        {
-                            // [>+<-]
-                            out.push(Token::Add(*there, *mul));
-
-                            si += 6;
-                            continue;
-
-                        // [>+ ><
-                        // Dup
-                        } else if let Some(Token::Inc(mul2)) = next {
-                            // [>+ >< +
-                            if let Some(Token::Goto(bi)) = toks.get(si + 5) {
-                                if *bi == -(there + back)
-                                    // [>+>+<<
-                                    && matches!(toks.get(si + 6), Some(Token::Dec(1)))
-                                    // [>+>+<<-
-                                    && matches!(toks.get(si + 7), Some(Token::RBrack(_)))
-                                // [>+>+<<-]
-                                {
-                                    out.push(Token::Dup(*there, *mul, *back, *mul2));
-
-                                    si += 8;
-                                    continue;
-                                }
-                            }
-                        }
-                    }
+                    // Scan
+                    } else if matches!(next, Some(Token::RBrack(_))) {
+                        out.push(Token::Scan(*there));
 
-                // Sub
-                } else if matches!(next, Some(Token::Dec(1))) {
-                    if let Some(Token::Goto(back)) = toks.get(si + 3) {
-                        if *there == -back
-                            && matches!(toks.get(si + 4), Some(Token::Dec(1)))
-                            && matches!(toks.get(si + 5), Some(Token::RBrack(_)))
-                        {
-                            out.push(Token::Sub(*there));
-
-                            si += 6;
-                            continue;
-                        }
+                        si += 3;
+                        break 'tok;
                     }
 
-                // Scan
-                } else if matches!(next, Some(Token::RBrack(_))) {
-                    out.push(Token::Scan(*there));
+                // End
+                } else if let Some(Token::RBrack(_)) = next {
+                    out.push(Token::End);
 
-                    si += 3;
-                    continue;
+                    si += 2;
+                    break 'tok;
                 }
-
-            // End
-            } else if let Some(Token::RBrack(_)) = next {
-                out.push(Token::End);
-
-                si += 2;
-                continue;
             }
-        }
 
-        match tok {
-            Token::LBrack(_) => lbracks.push(out.len()),
-            Token::RBrack(_) => {
-                let lb = lbracks
-                    .pop()
-                    .unwrap_or_else(|| err("invalid input", "unmatched closing bracket"));
-                out[lb] = Token::LBrack(out.len());
-                tok = Token::RBrack(lb);
-            }
+            match tok {
+                Token::LBrack(_) => lbracks.push(out.len()),
+                Token::RBrack(_) => {
+                    let lb = lbracks.pop().unwrap_or_else(|| {
+                        fail(Failure::Parse, "invalid input", "unmatched closing bracket")
+                    });
+                    out[lb] = Token::LBrack(out.len());
+                    tok = Token::RBrack(lb);
+                }
+
+                Token::Goto(0) | Token::Inc(0) | Token::Dec(0) => {
+                    si += 1;
+                    break 'tok;
+                }
 
-            Token::Goto(0) | Token::Inc(0) | Token::Dec(0) => {
-                si += 1;
-                continue;
+                _ => {}
             }
 
-            _ => {}
+            out.push(tok);
+            si += 1;
         }
 
-        out.push(tok);
-        si += 1;
+        ranges.resize(out.len(), (start, si));
     }
 
     if !lbracks.is_empty() {
-        err("invalid input", "unmatched opening bracket");
+        fail(Failure::Parse, "invalid input", "unmatched opening bracket");
     }
 
-    out
+    (out, ranges)
 }
 
 impl Display for Token {
@@ -329,12 +479,12 @@ impl Display for Token {
             Token::Zero => write!(f, "[-]"),
             Token::Add(i, mul) => write!(
                 f,
-                "[{}-{}{}]",
+                "[-{}{}{}]",
                 left_right(*i),
+                "+".repeat(*mul as usize),
                 left_right(-i),
-                "+".repeat(*mul as usize)
             ),
-            Token::Sub(i) => write!(f, "[{}-{}-]", left_right(*i), left_right(-i)),
+            Token::Sub(i) => write!(f, "[-{}-{}]", left_right(*i), left_right(-i)),
             Token::Dup(i1, mul1, i2, mul2) => write!(
                 f,
                 "[-{}{}{}{}{}]",
@@ -348,7 +498,6 @@ impl Display for Token {
 
             Token::End => write!(f, "[]"),
 
-            #[cfg(any(debug_assertions, feature = "debug"))]
             Token::Dump => write!(f, ";"),
         }
     }
@@ -0,0 +1,170 @@
+//! Execution traces, for comparing brim's behavior against other
+//! interpreters.
+//!
+//! Each traced token is written as one line, after it runs:
+//!
+//! ```text
+//! <step> <ip> <sp> <cell> <token>
+//! ```
+//!
+//! where `sp` and `cell` are the tape pointer and current cell afterwards, and
+//! `token` is the token's mnemonic (see [`ir`](crate::emit::ir)).
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::emit::ir::mnemonic;
+use crate::machine::{Machine, Observer};
+use crate::program::Program;
+use crate::token::Token;
+
+/// Restricts which tokens are traced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    /// Only input and output (`io`).
+    Io,
+    /// Only entries into a loop body (`loops`).
+    Loops,
+    /// Only tokens starting between two source positions, each given as a
+    /// 1-based line and column. The end is exclusive.
+    ///
+    /// Written as `line:col..line:col`; a bare `line` stands for the start of
+    /// that line, or for the whole line at the end, so `10..20` means lines
+    /// 10 through 20.
+    Source((usize, usize), (usize, usize)),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn position(s: &str, end: bool) -> Option<(usize, usize)> {
+            match s.split_once(':') {
+                Some((line, col)) => Some((line.parse().ok()?, col.parse().ok()?)),
+                None if end => Some((s.parse::<usize>().ok()? + 1, 1)),
+                None => Some((s.parse().ok()?, 1)),
+            }
+        }
+
+        match s {
+            "io" => Ok(Self::Io),
+            "loops" => Ok(Self::Loops),
+            _ => {
+                let range = s.split_once("..").unwrap_or((s, s));
+
+                match (position(range.0, false), position(range.1, true)) {
+                    (Some(start), Some(end)) => Ok(Self::Source(start, end)),
+                    _ => Err(format!(
+                        "expected `io`, `loops`, or a source range like `3..5` or `3:1..4:10`, found `{s}`"
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// Whether to trace a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rule {
+    Never,
+    Always,
+    /// Only if it's an opening bracket that enters its loop.
+    Entry,
+}
+
+/// Writes a line to `out` for each token run. If there are any filters, only
+/// tokens matching at least one of them are written.
+pub struct Trace<'a, W: Write> {
+    program: &'a Program,
+    out: W,
+    rules: Vec<Rule>,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: Write> Trace<'a, W> {
+    /// Creates a trace of `program`, writing to `out`.
+    pub fn new(program: &'a Program, out: W, filters: &[Filter]) -> Self {
+        let positions = program.positions();
+        let rules = program
+            .code
+            .iter()
+            .enumerate()
+            .map(|(i, tok)| {
+                let mut rule = if filters.is_empty() {
+                    Rule::Always
+                } else {
+                    Rule::Never
+                };
+
+                for filter in filters {
+                    let matched = match *filter {
+                        Filter::Io => matches!(tok, Token::In | Token::Out),
+                        Filter::Loops => {
+                            if matches!(tok, Token::LBrack(_)) && rule == Rule::Never {
+                                rule = Rule::Entry;
+                            }
+                            false
+                        }
+                        Filter::Source(start, end) => positions
+                            .get(i)
+                            .is_some_and(|pos| (start..end).contains(pos)),
+                    };
+
+                    if matched {
+                        rule = Rule::Always;
+                    }
+                }
+
+                rule
+            })
+            .collect();
+
+        Self {
+            program,
+            out,
+            rules,
+            error: None,
+        }
+    }
+
+    /// Flushes the trace, returning the first error encountered while writing
+    /// it, if any.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Observer for Trace<'_, W> {
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        let traced = match self.rules[ip] {
+            Rule::Never => false,
+            Rule::Always => true,
+            // A loop is only entered if its opening bracket doesn't jump.
+            Rule::Entry => machine.ip == ip + 1,
+        };
+
+        if !traced || self.error.is_some() {
+            return true;
+        }
+
+        let tok = &self.program.code[ip];
+
+        let sp = machine.sp;
+        if let Err(e) = writeln!(
+            self.out,
+            "{} {ip} {sp} {} {}",
+            machine.steps,
+            machine.cell(sp),
+            mnemonic(tok)
+        ) {
+            self.error = Some(e);
+        }
+
+        true
+    }
+}
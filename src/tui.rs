@@ -0,0 +1,455 @@
+//! A full-screen terminal UI for watching programs run.
+//!
+//! Shows the source with the current token highlighted, a strip of the tape
+//! around the pointer, and the program's output so far. Uses nothing but ANSI
+//! escape codes, and `stty` to put the terminal into raw mode, so it only
+//! works on Unix-like systems.
+
+use std::fmt::Write as _;
+use std::io::{self, stdin, stdout, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::machine::{Machine, State};
+use crate::program::Program;
+
+/// Steps per second at each speed setting. The last is as fast as possible.
+const SPEEDS: [u64; 10] = [1, 3, 10, 30, 100, 300, 1000, 10_000, 100_000, u64::MAX];
+/// How long to spend running between frames at full speed.
+const FRAME: Duration = Duration::from_millis(50);
+
+const HELP: &str = "space: play/pause  s: step  +/-: speed  r: restart  q: quit";
+
+/// Puts the terminal into raw mode and an alternate screen until dropped.
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+
+        // Return from reads after at most 0.1s, even with no input; this is
+        // what paces the UI.
+        stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "1"])?;
+
+        // Alternate screen, hidden cursor.
+        print!("\x1b[?1049h\x1b[?25l");
+        stdout().flush()?;
+
+        Ok(Self {
+            saved: saved.trim().to_string(),
+        })
+    }
+
+    /// The number of rows and columns in the terminal.
+    fn size() -> (usize, usize) {
+        stty(&["size"])
+            .ok()
+            .and_then(|size| {
+                let (rows, cols) = size.trim().split_once(' ')?;
+                Some((rows.parse().ok()?, cols.parse().ok()?))
+            })
+            .filter(|&(rows, cols)| rows != 0 && cols != 0)
+            .unwrap_or((24, 80))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Runs `stty` on the terminal, returning what it prints.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let out = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !out.status.success() {
+        return Err(io::Error::other("`stty` failed; is stdin a terminal?"));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// A full-screen, interactive view of a running program.
+pub struct Tui<'a> {
+    program: &'a Program,
+    name: String,
+    machine: Machine<'a>,
+    state: State,
+
+    /// The program's input: either a whole file, or whatever has been typed
+    /// so far.
+    input: Vec<u8>,
+    /// How much of `input` the program has read.
+    pos: usize,
+    /// Whether the input ends after `input`, rather than waiting for keys.
+    eof: bool,
+    output: Vec<u8>,
+
+    playing: bool,
+    /// Whether the program tried to read past the typed input, so keys are
+    /// fed to it rather than treated as commands.
+    blocked: bool,
+    speed: usize,
+    owed: f64,
+    size: (usize, usize),
+}
+
+impl<'a> Tui<'a> {
+    /// Creates a UI for `program`, whose source is left blank if it was loaded
+    /// from bytecode. The program reads any input embedded in its source, then
+    /// `input` if given; otherwise, it's typed in as needed.
+    pub fn new(program: &'a Program, name: &str, input: Option<Vec<u8>>) -> Self {
+        let eof = input.is_some();
+        let input = [program.input.clone(), input.unwrap_or_default()].concat();
+
+        Self {
+            program,
+            name: name.to_string(),
+            machine: Self::machine(program),
+            state: State::Running,
+            input,
+            pos: 0,
+            eof,
+            output: Vec::new(),
+            playing: false,
+            blocked: false,
+            speed: 4,
+            owed: 0.0,
+            size: (24, 80),
+        }
+    }
+
+    fn machine(program: &'a Program) -> Machine<'a> {
+        let mut machine = Machine::new(&program.code);
+        // Dumps would scribble over the screen.
+        machine.dump.out = Some(Box::new(io::sink()));
+        machine
+    }
+
+    /// Everything the program has output so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Takes over the terminal until the user quits.
+    pub fn run(&mut self) -> io::Result<()> {
+        let _terminal = Terminal::enter()?;
+        let mut last_resize = Instant::now() - Duration::from_secs(1);
+        let mut last_frame = Instant::now();
+        let mut keys = [0; 16];
+
+        loop {
+            if last_resize.elapsed() >= Duration::from_secs(1) {
+                self.size = Terminal::size();
+                last_resize = Instant::now();
+            }
+
+            self.render()?;
+
+            let n = stdin().read(&mut keys)?;
+            if !self.keys(&keys[..n]) {
+                return Ok(());
+            }
+
+            let elapsed = last_frame.elapsed();
+            last_frame = Instant::now();
+
+            if self.playing {
+                self.play(elapsed);
+            } else {
+                self.owed = 0.0;
+            }
+        }
+    }
+
+    /// Handles a batch of key presses. Returns false to quit.
+    fn keys(&mut self, keys: &[u8]) -> bool {
+        let mut keys = keys.iter().copied();
+
+        while let Some(key) = keys.next() {
+            // Ctrl-C always quits, since signals are off in raw mode.
+            if key == 3 {
+                return false;
+            }
+
+            if self.blocked {
+                match key {
+                    // Ctrl-D
+                    4 => self.eof = true,
+                    key => self.input.push(key),
+                }
+
+                // When paused, this was a step waiting on input, so finish it.
+                if !self.playing {
+                    self.step();
+                }
+
+                continue;
+            }
+
+            match key {
+                b'q' => return false,
+                b' ' => self.playing = !self.playing,
+                b's' | b'n' => {
+                    self.playing = false;
+                    self.step();
+                }
+                b'+' | b'=' => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+                b'-' | b'_' => self.speed = self.speed.saturating_sub(1),
+                b'r' => self.restart(),
+
+                // Arrow keys: right steps, up/down change speed.
+                0x1b if keys.next() == Some(b'[') => match keys.next() {
+                    Some(b'C') => {
+                        self.playing = false;
+                        self.step();
+                    }
+                    Some(b'A') => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+                    Some(b'B') => self.speed = self.speed.saturating_sub(1),
+                    _ => {}
+                },
+
+                _ => {}
+            }
+        }
+
+        true
+    }
+
+    /// Runs as many steps as the speed calls for in `elapsed` time.
+    fn play(&mut self, elapsed: Duration) {
+        let speed = SPEEDS[self.speed];
+        let start = Instant::now();
+
+        if speed == u64::MAX {
+            while start.elapsed() < FRAME {
+                for _ in 0..10_000 {
+                    if !self.step() {
+                        return;
+                    }
+                }
+            }
+
+            return;
+        }
+
+        self.owed += elapsed.as_secs_f64() * speed as f64;
+        while self.owed >= 1.0 && start.elapsed() < FRAME {
+            if !self.step() {
+                break;
+            }
+
+            self.owed -= 1.0;
+        }
+
+        self.owed = self.owed.min(speed as f64);
+    }
+
+    /// Whether the program needs more input than has been typed.
+    fn waiting(&self) -> bool {
+        self.state == State::Running
+            && !self.eof
+            && self.pos == self.input.len()
+            && self.program.code.get(self.machine.ip) == Some(&crate::token::Token::In)
+    }
+
+    /// Runs a single token, pausing if the program stops. Returns false if it
+    /// didn't run, including if it's blocked on input.
+    fn step(&mut self) -> bool {
+        if self.state != State::Running {
+            self.playing = false;
+            return false;
+        }
+
+        if self.waiting() {
+            self.blocked = true;
+            return false;
+        }
+
+        self.blocked = false;
+
+        let mut input = self.input[self.pos..].iter().copied();
+        self.state = self.machine.step(&mut input, &mut self.output);
+        self.pos = self.input.len() - input.len();
+
+        if self.state != State::Running {
+            self.playing = false;
+        }
+
+        true
+    }
+
+    /// Starts the program over, with the same input.
+    fn restart(&mut self) {
+        self.machine = Self::machine(self.program);
+        self.state = State::Running;
+        self.pos = 0;
+        self.output.clear();
+        self.playing = false;
+        self.blocked = false;
+    }
+
+    /// Draws the whole screen.
+    fn render(&self) -> io::Result<()> {
+        let (rows, cols) = self.size;
+        let output_rows = 5;
+        let source_rows = rows.saturating_sub(output_rows + 8).max(1);
+
+        let mut screen = String::from("\x1b[H");
+
+        let status = match self.state {
+            State::Finished => "finished",
+            State::Hanging => "hanging",
+            _ if self.blocked => "waiting for input (type, or ^D for EOF)",
+            _ if self.playing => "playing",
+            _ => "paused",
+        };
+        let speed = match SPEEDS[self.speed] {
+            u64::MAX => "max".to_string(),
+            speed => format!("{speed}/s"),
+        };
+        let title = format!(
+            " brim: {} | {status} | speed {speed} | step {} | sp {}",
+            self.name, self.machine.steps, self.machine.sp
+        );
+        line(&mut screen, &format!("\x1b[7m{}\x1b[0m", pad(&title, cols)));
+
+        self.render_source(&mut screen, source_rows, cols);
+
+        line(&mut screen, &rule("tape", cols));
+        self.render_tape(&mut screen, cols);
+
+        line(&mut screen, &rule("output", cols));
+        self.render_output(&mut screen, output_rows, cols);
+
+        screen.push_str(&format!(
+            "\x1b[2m{}\x1b[0m\x1b[K\x1b[J",
+            truncate(HELP, cols)
+        ));
+
+        let mut out = stdout().lock();
+        out.write_all(screen.as_bytes())?;
+        out.flush()
+    }
+
+    /// Draws the source around the current token, highlighting it.
+    fn render_source(&self, screen: &mut String, rows: usize, cols: usize) {
+        let source = &self.program.source;
+        let span = self.program.spans.get(self.machine.ip).copied();
+        let current = span.map_or(0, |span| source[..span.start].matches('\n').count());
+        let first = current.saturating_sub(rows / 2);
+
+        let mut offset = 0;
+        let mut shown = 0;
+        for (n, text) in source.split('\n').enumerate() {
+            let start = offset;
+            offset += text.len() + 1;
+
+            if n < first {
+                continue;
+            }
+            if shown == rows {
+                break;
+            }
+            shown += 1;
+
+            let mut out = format!("{:>5} ", n + 1);
+            for (i, ch) in text.char_indices().take(cols.saturating_sub(6)) {
+                let ch = if ch.is_control() { ' ' } else { ch };
+
+                if span.is_some_and(|span| (span.start..span.end).contains(&(start + i))) {
+                    let _ = write!(out, "\x1b[7m{ch}\x1b[0m");
+                } else {
+                    out.push(ch);
+                }
+            }
+
+            line(screen, &out);
+        }
+
+        for _ in shown..rows {
+            line(screen, "");
+        }
+    }
+
+    /// Draws the cells around the pointer, with their indices.
+    fn render_tape(&self, screen: &mut String, cols: usize) {
+        const WIDTH: usize = 6;
+
+        let sp = self.machine.sp;
+        let count = (cols / WIDTH).max(1);
+        let start = sp.saturating_sub(count / 2);
+        let end = if cfg!(feature = "dynamic_array") {
+            start + count
+        } else {
+            (start + count).min(self.machine.tape.len())
+        };
+
+        let (mut indices, mut values, mut pointer) = (String::new(), String::new(), String::new());
+        for i in start..end {
+            let _ = write!(indices, "{i:>WIDTH$}");
+
+            let value = format!("{:>WIDTH$}", self.machine.cell(i));
+            if i == sp {
+                let _ = write!(values, "\x1b[7m{value}\x1b[0m");
+                let _ = write!(pointer, "{:>WIDTH$}", "^");
+            } else {
+                values.push_str(&value);
+                pointer.push_str(&" ".repeat(WIDTH));
+            }
+        }
+
+        line(screen, &format!("\x1b[2m{indices}\x1b[0m"));
+        line(screen, &values);
+        line(screen, &pointer);
+    }
+
+    /// Draws the last few lines of output.
+    fn render_output(&self, screen: &mut String, rows: usize, cols: usize) {
+        let text = String::from_utf8_lossy(&self.output);
+        let lines: Vec<&str> = text.split('\n').collect();
+        let skip = lines.len().saturating_sub(rows);
+
+        for text in &lines[skip..] {
+            let text: String = text
+                .chars()
+                .map(|ch| if ch.is_control() { ' ' } else { ch })
+                .collect();
+            line(screen, &truncate(&text, cols));
+        }
+
+        for _ in lines.len() - skip..rows {
+            line(screen, "");
+        }
+    }
+}
+
+/// Adds a line to the screen, clearing whatever was there before.
+fn line(screen: &mut String, text: &str) {
+    screen.push_str(text);
+    screen.push_str("\x1b[K\r\n");
+}
+
+/// A horizontal rule with a label.
+fn rule(label: &str, cols: usize) -> String {
+    let text = format!("-- {label} ");
+    let len = text.chars().count();
+    format!("{text}{}", "-".repeat(cols.saturating_sub(len)))
+}
+
+fn truncate(text: &str, cols: usize) -> String {
+    text.chars().take(cols).collect()
+}
+
+fn pad(text: &str, cols: usize) -> String {
+    format!("{:cols$}", truncate(text, cols))
+}
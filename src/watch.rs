@@ -0,0 +1,91 @@
+//! Watchpoints: noticing when cells change, and which token changed them.
+
+use std::ops::Range;
+
+use crate::machine::{Machine, Observer};
+use crate::program::Program;
+use crate::Cell;
+
+/// A change to a watched cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    /// The cell that changed.
+    pub cell: usize,
+    /// Its value before the change.
+    pub old: Cell,
+    /// Its value after the change.
+    pub new: Cell,
+    /// The index of the token responsible.
+    pub ip: usize,
+}
+
+impl Hit {
+    /// Describes the change, e.g. `cell 37: 0 -> 5 by #12 (3:5) add [+1] *1`.
+    pub fn describe(&self, program: &Program) -> String {
+        format!(
+            "cell {}: {} -> {} by {}",
+            self.cell,
+            self.old,
+            self.new,
+            program.describe(self.ip)
+        )
+    }
+}
+
+/// Watches ranges of cells for changes, including those made indirectly by
+/// macro-tokens like [`Add`](crate::token::Token::Add).
+///
+/// Writes that leave a cell's value unchanged aren't reported.
+#[derive(Clone, Debug, Default)]
+pub struct Watch {
+    /// The cells being watched.
+    pub ranges: Vec<Range<usize>>,
+    /// Whether to stop the machine after a watched cell changes.
+    pub stop: bool,
+    /// The changes seen so far, oldest first. Drain this as needed.
+    pub hits: Vec<Hit>,
+
+    pending: Vec<(usize, Cell)>,
+}
+
+impl Watch {
+    /// Creates a watch over no cells.
+    pub fn new(stop: bool) -> Self {
+        Self {
+            stop,
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if `cell` is being watched.
+    pub fn watches(&self, cell: usize) -> bool {
+        self.ranges.iter().any(|range| range.contains(&cell))
+    }
+}
+
+impl Observer for Watch {
+    fn before(&mut self, machine: &Machine) -> bool {
+        self.pending.clear();
+
+        for cell in machine.writes() {
+            if self.watches(cell) && !self.pending.iter().any(|&(c, _)| c == cell) {
+                self.pending.push((cell, machine.cell(cell)));
+            }
+        }
+
+        true
+    }
+
+    fn after(&mut self, machine: &Machine, ip: usize) -> bool {
+        let before = self.hits.len();
+
+        for &(cell, old) in &self.pending {
+            let new = machine.cell(cell);
+            if new != old {
+                self.hits.push(Hit { cell, old, new, ip });
+            }
+        }
+
+        !self.stop || self.hits.len() == before
+    }
+}
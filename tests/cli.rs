@@ -0,0 +1,136 @@
+//! Runs the `brim` binary, checking how it exits.
+
+use std::fs;
+use std::process::{Command, Output, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Runs `brim` with `args`, killing it if it takes longer than 10 seconds.
+fn brim(args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_brim"))
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("`brim {}` didn't exit", args.join(" "));
+        }
+
+        sleep(Duration::from_millis(10));
+    }
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn max_steps_stops_a_hang() {
+    let out = brim(&["-e", "+[]", "--max-steps", "10"]);
+    assert_eq!(out.status.code(), Some(4));
+}
+
+#[test]
+fn timeout_stops_a_hang() {
+    let out = brim(&["-e", "+[]", "--timeout", "5"]);
+    assert_eq!(out.status.code(), Some(5));
+}
+
+#[test]
+fn max_steps_stops_a_loop() {
+    let out = brim(&["-e", "+[>+<]", "--max-steps", "10"]);
+    assert_eq!(out.status.code(), Some(4));
+}
+
+#[test]
+fn exit_code_is_the_current_cell() {
+    let out = brim(&["-e", "+++>++", "--exit-code"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    let out = brim(&["-e", "+++>++", "--exit-cell", "0"]);
+    assert_eq!(out.status.code(), Some(3));
+}
+
+#[test]
+fn failures_have_distinct_exit_codes() {
+    assert_eq!(brim(&["-e", "[["]).status.code(), Some(2));
+    assert_eq!(brim(&["does-not-exist.bf"]).status.code(), Some(3));
+}
+
+// With `dynamic_array`, the tape grows to fit instead.
+#[cfg(not(feature = "dynamic_array"))]
+#[test]
+fn tape_overflow_has_its_own_exit_code() {
+    let out = brim(&["-e", "+", "--tape-pointer", "1000000"]);
+    assert_eq!(out.status.code(), Some(6));
+}
+
+// With `wide_cell`, `.` writes the whole cell, so the output isn't just `A`.
+#[cfg(not(feature = "wide_cell"))]
+#[test]
+fn test_keeps_going_after_a_parse_error() {
+    let dir = std::env::temp_dir().join(format!("brim-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.bf"), "++++++++[>++++++++<-]>+.").unwrap();
+    fs::write(dir.join("a.out"), "A").unwrap();
+    fs::write(dir.join("b.bf"), "[").unwrap();
+    fs::write(dir.join("b.out"), "").unwrap();
+
+    let out = brim(&["test", dir.to_str().unwrap()]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("PASS a.bf"), "{stdout}");
+    assert!(
+        stdout.contains("FAIL b.bf: unmatched opening bracket"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("2 tests: 1 passed, 1 failed"), "{stdout}");
+    assert_eq!(out.status.code(), Some(1));
+}
+
+#[test]
+fn dump_tape_takes_one_machine() {
+    let dir = std::env::temp_dir().join(format!("brim-tape-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let prog = dir.join("prog.bf");
+    let tape = dir.join("tape.json");
+    fs::write(&prog, "+").unwrap();
+
+    let (prog, tape) = (prog.to_str().unwrap(), tape.to_str().unwrap());
+    let apart = brim(&["-e", "+", prog, "--dump-tape", tape]);
+    let linked = brim(&["--link", "-e", "+", prog, "--dump-tape", tape]);
+    let saved = fs::read_to_string(tape).unwrap();
+    let loaded = brim(&["--tape-init", tape, "-e", "", "--exit-code"]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(apart.status.code(), Some(1));
+    assert!(linked.status.success());
+
+    // Only the final tape is saved, after both programs.
+    assert_eq!(saved.lines().count(), 1);
+    assert_eq!(loaded.status.code(), Some(2));
+}
+
+#[test]
+fn debug_takes_bytecode() {
+    let dir = std::env::temp_dir().join(format!("brim-bytecode-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let prog = dir.join("prog.bf");
+    let compiled = dir.join("prog.bfc");
+    fs::write(&prog, "+++.").unwrap();
+
+    let (prog, compiled) = (prog.to_str().unwrap(), compiled.to_str().unwrap());
+    let compile = brim(&["compile", prog, "-o", compiled]);
+    let debug = brim(&["debug", compiled]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(compile.status.success());
+    let stderr = String::from_utf8_lossy(&debug.stderr);
+    assert!(debug.status.success(), "{stderr}");
+    assert!(stderr.contains("next: #0"), "{stderr}");
+}
@@ -0,0 +1,75 @@
+//! Checks that running programs hasn't slowed down. These take a while, so
+//! they're ignored by default; run them with
+//! `cargo test --release -- --ignored`.
+
+use std::io::sink;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use brim::machine::Machine;
+use brim::program::Program;
+
+const MANDELBROT: &str = include_str!("../mandelbrot.bf");
+
+/// Times the fastest of a few calls to `run`.
+fn best(mut run: impl FnMut()) -> Duration {
+    (0..2)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// Running a program from the command line, with no options, should be as
+/// fast as running it on a bare [`Machine`].
+#[test]
+#[ignore]
+fn command_line_adds_no_overhead() {
+    let program = Program::new(MANDELBROT.to_string());
+
+    let bare = best(|| {
+        Machine::new(&program.code).run(&mut std::iter::empty(), &mut sink());
+    });
+    let cli = best(|| {
+        let status = Command::new(env!("CARGO_BIN_EXE_brim"))
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/mandelbrot.bf"))
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    });
+
+    assert!(
+        cli.as_secs_f64() <= bare.as_secs_f64() * 1.15,
+        "`brim mandelbrot.bf` took {cli:?}, but a bare machine took {bare:?}"
+    );
+}
+
+/// The interpreter should run at least `BRIM_MIN_STEPS_PER_SEC` steps per
+/// second, if set (e.g. from a previous run of `brim bench` on the same
+/// machine).
+#[test]
+#[ignore]
+fn steps_per_sec() {
+    let Ok(min) = std::env::var("BRIM_MIN_STEPS_PER_SEC") else {
+        return;
+    };
+    let min: f64 = min.parse().expect("invalid BRIM_MIN_STEPS_PER_SEC");
+
+    let program = Program::new(MANDELBROT.to_string());
+    let mut steps = 0;
+    let time = best(|| {
+        let mut machine = Machine::new(&program.code);
+        machine.run(&mut std::iter::empty(), &mut sink());
+        steps = machine.steps;
+    });
+
+    let rate = steps as f64 / time.as_secs_f64();
+    assert!(
+        rate >= min,
+        "ran {rate:.0} steps/s, expected at least {min}"
+    );
+}